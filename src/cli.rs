@@ -0,0 +1,79 @@
+use clap::Parser;
+use std::path::PathBuf;
+
+/// Commit all changes in 4 key presses
+#[derive(Parser, Debug)]
+#[command(name = "qc", version, about)]
+pub struct Args {
+    /// Use the given message instead of prompting (repeat for extra paragraphs)
+    #[arg(short, long, value_name = "MSG")]
+    pub message: Vec<String>,
+
+    /// Write the message in your editor, starting from -m if given
+    #[arg(short, long, conflicts_with = "yes")]
+    pub edit: bool,
+
+    /// Pick which files to commit before staging
+    #[arg(short, long, conflicts_with = "yes")]
+    pub interactive: bool,
+
+    /// Pick the hunks of modified files to commit, like `git add -p`
+    #[arg(short, long, conflicts_with_all = ["interactive", "dry_run", "yes"])]
+    pub patch: bool,
+
+    /// Fold the changes into the last commit, keeping its message by default
+    #[arg(long)]
+    pub amend: bool,
+
+    /// Commit without pushing
+    #[arg(long)]
+    pub no_push: bool,
+
+    /// Show what would be committed without committing or pushing
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+
+    /// Build a Conventional Commits message (or set qc.conventional)
+    #[arg(long)]
+    pub conventional: bool,
+
+    /// Show the diff before asking for a message (or type ? at the prompt)
+    #[arg(long)]
+    pub diff: bool,
+
+    /// Commit even if the changes look like they contain secrets
+    #[arg(long)]
+    pub allow_secrets: bool,
+
+    /// Commit even if files are over qc.maxSize or blocked binaries
+    #[arg(long)]
+    pub allow_large: bool,
+
+    /// Skip the pre-commit and commit-msg hooks
+    #[arg(long)]
+    pub no_verify: bool,
+
+    /// Never prompt; without -m use qc.fallbackMessage or fail
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Only stage and commit changes matching these pathspecs
+    #[arg(value_name = "PATHSPEC")]
+    pub pathspec: Vec<String>,
+
+    /// Run as if qc was started in <PATH>
+    #[arg(short = 'C', value_name = "PATH", default_value = ".")]
+    pub directory: PathBuf,
+}
+
+impl Args {
+    /// The commit message given on the command line, if any. Multiple
+    /// `-m` values are joined as separate paragraphs, like `git commit`.
+    pub fn message(&self) -> Option<String> {
+        if self.message.is_empty() {
+            None
+        } else {
+            Some(self.message.join("\n\n"))
+        }
+    }
+}
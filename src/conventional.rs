@@ -0,0 +1,159 @@
+use crate::{ask, StagedFile};
+use colored::*;
+use git2::Repository;
+use regex::Regex;
+
+const DEFAULT_TYPES: &[&str] = &[
+    "feat", "fix", "docs", "style", "refactor", "perf", "test", "build", "ci", "chore", "revert",
+];
+
+/// Whether to use Conventional Commits, from `--conventional` or
+/// `qc.conventional`.
+pub fn enabled(repo: &Repository, flag: bool) -> bool {
+    flag || repo
+        .config()
+        .and_then(|config| config.get_bool("qc.conventional"))
+        .unwrap_or(false)
+}
+
+/// Allowed types from `qc.types` (comma or space separated), or the usual ones.
+fn types(repo: &Repository) -> Vec<String> {
+    repo.config()
+        .and_then(|config| config.get_string("qc.types"))
+        .map(|types| {
+            types
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|kind| !kind.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .ok()
+        .filter(|types| !types.is_empty())
+        .unwrap_or_else(|| DEFAULT_TYPES.iter().map(|kind| kind.to_string()).collect())
+}
+
+/// Ask for type, scope, breaking change and subject, and put the header
+/// together. `None` if input ran out.
+pub fn prompt(repo: &Repository, files: &[StagedFile]) -> Option<String> {
+    let types = types(repo);
+
+    println!("{}", types.join(" · ").italic());
+    let kind = loop {
+        let answer = ask("type: ")?;
+        // complete unique prefixes, "fe" is "feat"
+        let matches: Vec<&String> = types
+            .iter()
+            .filter(|kind| kind.starts_with(&answer))
+            .collect();
+        match matches.as_slice() {
+            _ if types.contains(&answer) => break answer,
+            [kind] if !answer.is_empty() => break kind.to_string(),
+            [] => println!("{}", format!("Unknown type '{}'", answer).yellow()),
+            _ => println!(
+                "{}",
+                format!(
+                    "'{}' could be {}",
+                    answer,
+                    matches
+                        .iter()
+                        .map(|kind| kind.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+                .yellow()
+            ),
+        }
+    };
+
+    let inferred = infer_scope(files);
+    let scope = match &inferred {
+        Some(inferred) => {
+            let answer = ask(&format!("scope ({}, - for none): ", inferred))?;
+            match answer.as_str() {
+                "" => inferred.clone(),
+                "-" => String::new(),
+                _ => answer,
+            }
+        }
+        None => ask("scope (optional): ")?,
+    };
+
+    let breaking = ask("breaking change? [y/N]: ")?
+        .to_lowercase()
+        .starts_with('y');
+    let breaking_note = if breaking {
+        ask("describe the breaking change (optional): ")?
+    } else {
+        String::new()
+    };
+
+    let subject = loop {
+        let subject = ask("subject: ")?;
+        if !subject.is_empty() {
+            break subject;
+        }
+    };
+
+    let mut message = kind;
+    if !scope.is_empty() {
+        message.push_str(&format!("({})", scope));
+    }
+    if breaking {
+        message.push('!');
+    }
+    message.push_str(&format!(": {}", subject));
+    if !breaking_note.is_empty() {
+        message.push_str(&format!("\n\nBREAKING CHANGE: {}", breaking_note));
+    }
+
+    Some(message)
+}
+
+/// Check the first line is a valid Conventional Commits header with one of
+/// the allowed types.
+pub fn validate(repo: &Repository, message: &str) -> Result<(), String> {
+    let header = message.lines().next().unwrap_or_default();
+    let pattern = Regex::new(r"^([a-zA-Z]+)(\([^()\s][^()]*\))?!?: \S").unwrap();
+
+    let kind = match pattern.captures(header) {
+        Some(captures) => captures[1].to_string(),
+        None => {
+            return Err(format!(
+                "'{}' is not a Conventional Commits header, expected 'type(scope): subject'",
+                header
+            ))
+        }
+    };
+
+    let types = types(repo);
+    if !types.contains(&kind) {
+        return Err(format!(
+            "unknown type '{}', expected one of {}",
+            kind,
+            types.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// The innermost directory every changed file is in, as the scope.
+fn infer_scope(files: &[StagedFile]) -> Option<String> {
+    let mut common: Option<Vec<&str>> = None;
+
+    for file in files {
+        let mut dirs: Vec<&str> = file.path.split('/').collect();
+        dirs.pop(); // the file name
+        common = Some(match common {
+            None => dirs,
+            Some(common) => common
+                .iter()
+                .zip(&dirs)
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| *a)
+                .collect(),
+        });
+    }
+
+    common?.last().map(|dir| dir.to_string())
+}
@@ -0,0 +1,195 @@
+use crate::filters;
+use colored::*;
+use git2::{Delta, Diff, DiffDelta, Error, Patch, Repository};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use terminal_size::{terminal_size, Width};
+
+/// Lines added and removed in one file of a diff.
+#[derive(Clone, Copy, Default)]
+pub struct FileStat {
+    pub insertions: usize,
+    pub deletions: usize,
+    pub binary: bool,
+    /// Counted from the working tree file of a dry run, which a clean filter
+    /// (e.g. Git LFS) would turn into something else
+    pub approximate: bool,
+}
+
+impl FileStat {
+    fn changes(&self) -> usize {
+        self.insertions + self.deletions
+    }
+}
+
+/// Per-file stats of a diff, keyed by the new path of each file.
+pub fn per_file(repo: &Repository, diff: &Diff) -> Result<HashMap<String, FileStat>, Error> {
+    let odb = repo.odb()?;
+    let mut stats = HashMap::new();
+
+    for idx in 0..diff.deltas().len() {
+        let patch = Patch::from_diff(diff, idx)?;
+        let delta = match &patch {
+            Some(patch) => patch.delta(),
+            None => diff.get_delta(idx).unwrap(),
+        };
+        let path = match delta.new_file().path().or_else(|| delta.old_file().path()) {
+            Some(path) => path.display().to_string(),
+            None => continue,
+        };
+
+        let mut stat = match &patch {
+            Some(_) if delta.status() == Delta::Typechange => typechange(repo, &delta)?,
+            Some(patch) if !delta.flags().is_binary() => {
+                let (_, insertions, deletions) = patch.line_stats()?;
+                FileStat {
+                    insertions,
+                    deletions,
+                    ..FileStat::default()
+                }
+            }
+            _ => FileStat {
+                binary: true,
+                ..FileStat::default()
+            },
+        };
+        // a dry run doesn't clean the file, so it isn't in the object database
+        let new_file = delta.new_file();
+        if let (false, Some(new_path)) = (delta.status() == Delta::Deleted, new_file.path()) {
+            stat.approximate =
+                !odb.exists(new_file.id()) && filters::clean_command(repo, new_path)?.is_some();
+        }
+        stats.insert(path, stat);
+    }
+
+    Ok(stats)
+}
+
+/// Stats of a typechange, which libgit2 gives no lines for. Like git, count
+/// it as the old contents removed and the new ones (or the link target) added.
+fn typechange(repo: &Repository, delta: &DiffDelta) -> Result<FileStat, Error> {
+    let old = repo.find_blob(delta.old_file().id())?;
+    let new_file = delta.new_file();
+    let new = match repo.find_blob(new_file.id()) {
+        Ok(blob) => blob.content().to_vec(),
+        // the working tree side of a dry run
+        Err(_) => {
+            let path = repo
+                .workdir()
+                .zip(new_file.path())
+                .map(|(workdir, path)| workdir.join(path))
+                .ok_or_else(|| Error::from_str("typechange without a path"))?;
+            let read = match fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    fs::read_link(&path).map(|target| target.display().to_string().into_bytes())
+                }
+                _ => fs::read(&path),
+            };
+            read.map_err(|e| Error::from_str(&format!("unable to read {}: {}", path.display(), e)))?
+        }
+    };
+
+    let patch =
+        Patch::from_blob_and_buffer(&old, delta.old_file().path(), &new, new_file.path(), None)?;
+    if patch.delta().flags().is_binary() {
+        return Ok(FileStat {
+            binary: true,
+            ..FileStat::default()
+        });
+    }
+    let (_, insertions, deletions) = patch.line_stats()?;
+    Ok(FileStat {
+        insertions,
+        deletions,
+        ..FileStat::default()
+    })
+}
+
+/// Print `label | count +++---` rows like `git diff --stat`, with the labels
+/// aligned and the bars scaled to fit the terminal. Approximate counts get a
+/// `~` and a note below.
+pub fn print(rows: &[(String, Color, Option<FileStat>)]) {
+    // $COLUMNS wins over the terminal, like git
+    let width = env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .or_else(|| terminal_size().map(|(Width(width), _)| width as usize))
+        .unwrap_or(80);
+
+    let max_changes = rows
+        .iter()
+        .filter_map(|(_, _, stat)| stat.map(|stat| stat.changes()))
+        .max()
+        .unwrap_or(0);
+    let approximate = rows
+        .iter()
+        .any(|(_, _, stat)| stat.is_some_and(|stat| stat.approximate));
+    let count_width = max_changes.to_string().len().max("Bin".len()) + usize::from(approximate);
+    let max_label = rows
+        .iter()
+        .map(|(label, _, _)| label.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    // " | " between label and count, and a space before the bar
+    let available = width.saturating_sub(count_width + 4);
+    let mut label_width = max_label;
+    let mut bar_width = max_changes;
+    if label_width + bar_width > available {
+        label_width = label_width.min(available * 5 / 8);
+        bar_width = available.saturating_sub(label_width);
+    }
+
+    let scale = |n: usize| {
+        if n == 0 || max_changes <= bar_width {
+            n
+        } else {
+            (n * bar_width / max_changes).max(1)
+        }
+    };
+
+    for (label, color, stat) in rows {
+        let label = truncate(label, label_width);
+        let padding = " ".repeat(label_width - label.chars().count());
+        print!("{}{}", label.color(*color), padding);
+
+        let mark = match stat {
+            Some(stat) if stat.approximate => "~",
+            _ => "",
+        };
+        match stat {
+            Some(stat) if stat.binary => {
+                print!(" | {:>width$}", format!("{}Bin", mark), width = count_width)
+            }
+            Some(stat) => print!(
+                " | {:>width$} {}{}",
+                format!("{}{}", mark, stat.changes()),
+                "+".repeat(scale(stat.insertions)).green(),
+                "-".repeat(scale(stat.deletions)).red(),
+                width = count_width
+            ),
+            None => {}
+        }
+        println!();
+    }
+    if approximate {
+        println!(
+            "{}",
+            "~ counted before the clean filter, the commit will differ".italic()
+        );
+    }
+}
+
+/// Keep the end of a long label, which holds the file name.
+fn truncate(label: &str, width: usize) -> String {
+    let len = label.chars().count();
+    if len <= width {
+        return label.to_string();
+    }
+    if width <= 3 {
+        return label.chars().skip(len - width).collect();
+    }
+    let tail: String = label.chars().skip(len - (width - 3)).collect();
+    format!("...{}", tail)
+}
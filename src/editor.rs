@@ -0,0 +1,215 @@
+use crate::{hooks, StagedFile};
+use git2::{Diff, DiffStatsFormat, Error, Repository};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// The editor git would use: `GIT_EDITOR`, `core.editor`, `VISUAL`,
+/// `EDITOR`, then `vi`.
+fn command(repo: &Repository) -> String {
+    env::var("GIT_EDITOR")
+        .ok()
+        .or_else(|| repo.config().ok()?.get_string("core.editor").ok())
+        .or_else(|| env::var("VISUAL").ok())
+        .or_else(|| env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Open `path` in the editor and wait for it to close.
+pub fn edit(repo: &Repository, path: &Path) -> Result<(), Error> {
+    let editor = command(repo);
+
+    // Through the shell, editors are often configured with arguments
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(&editor)
+        .arg(path)
+        .status()
+        .map_err(|e| Error::from_str(&format!("unable to run editor '{}': {}", editor, e)))?;
+
+    if !status.success() {
+        return Err(Error::from_str(&format!(
+            "editor '{}' exited with {}",
+            editor, status
+        )));
+    }
+
+    Ok(())
+}
+
+const SCISSORS: &str = "------------------------ >8 ------------------------";
+
+/// The `commit.cleanup` mode and `core.commentChar`.
+fn cleanup(repo: &Repository) -> Result<(String, char), Error> {
+    let config = repo.config()?;
+    let comment = config
+        .get_string("core.commentChar")
+        .ok()
+        .and_then(|comment| comment.chars().next())
+        .filter(|&comment| comment != 'a') // "auto", pick the default
+        .unwrap_or('#');
+    let cleanup = config
+        .get_string("commit.cleanup")
+        .unwrap_or_else(|_| "default".to_string());
+
+    Ok((cleanup, comment))
+}
+
+/// The contents of the file `commit.template` points to, if one is set.
+pub fn template(repo: &Repository) -> Result<Option<String>, Error> {
+    let path = match repo.config()?.get_path("commit.template") {
+        Ok(path) => path,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let path = match repo.workdir() {
+        Some(workdir) if path.is_relative() => workdir.join(path),
+        _ => path,
+    };
+
+    fs::read_to_string(&path).map(Some).map_err(|e| {
+        Error::from_str(&format!(
+            "unable to read commit.template {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+/// The template's only line, if it has just one once cleaned up, to use as
+/// the start of the prompt.
+pub fn single_line(repo: &Repository, template: &str) -> Result<Option<String>, Error> {
+    let (mode, comment) = cleanup(repo)?;
+    let cleaned = clean(template, &mode, comment);
+    if cleaned.is_empty() || cleaned.contains('\n') {
+        return Ok(None);
+    }
+
+    // keep trailing spaces, "[PROJ-123] " is meant to be typed after
+    Ok(template
+        .lines()
+        .find(|line| line.trim_end() == cleaned)
+        .map(str::to_string))
+}
+
+/// Whether the message is just the template, which aborts the commit like
+/// it does in git.
+pub fn unchanged(repo: &Repository, message: &str, template: &str) -> Result<bool, Error> {
+    let (mode, comment) = cleanup(repo)?;
+    Ok(clean(message, &mode, comment) == clean(template, &mode, comment))
+}
+
+/// Write the commit message in the editor, starting from `seed` with the
+/// staged files and diffstat below it as comments. Like `git commit`, the
+/// prepare-commit-msg hook gets the file first, with `source` telling it where
+/// the message came from, and the result is cleaned up per `commit.cleanup`.
+pub fn message(
+    repo: &Repository,
+    seed: &str,
+    source: &[&str],
+    files: &[StagedFile],
+    diff: &Diff,
+) -> Result<String, Error> {
+    let (cleanup, comment) = cleanup(repo)?;
+
+    let mut text = format!("{}\n\n", seed.trim_end());
+    let mut commented = |line: &str| {
+        if line.is_empty() {
+            text.push_str(&format!("{}\n", comment));
+        } else {
+            text.push_str(&format!("{} {}\n", comment, line));
+        }
+    };
+    match cleanup.as_str() {
+        "strip" | "default" => {
+            commented("Please enter the commit message for your changes. Lines starting");
+            commented(&format!(
+                "with '{}' will be ignored, and an empty message aborts the commit.",
+                comment
+            ));
+        }
+        "scissors" => {
+            commented(SCISSORS);
+            commented("Do not modify or remove the line above.");
+            commented("Everything below it will be ignored.");
+        }
+        "whitespace" | "verbatim" => {
+            commented("Please enter the commit message for your changes. Lines starting");
+            commented(&format!(
+                "with '{}' will be kept; you may remove them yourself if you want to.",
+                comment
+            ));
+        }
+        other => {
+            return Err(Error::from_str(&format!(
+                "invalid commit.cleanup mode '{}'",
+                other
+            )))
+        }
+    }
+    commented("");
+    commented("Changes to be committed:");
+    for file in files {
+        commented(&format!("  {}", file.label()));
+    }
+    commented("");
+    let stats = diff.stats()?.to_buf(DiffStatsFormat::FULL, 72)?;
+    for line in stats.as_str().unwrap_or_default().lines() {
+        commented(line.trim_end());
+    }
+
+    let path = repo.path().join("COMMIT_EDITMSG");
+    fs::write(&path, &text).map_err(|e| Error::from_str(&e.to_string()))?;
+    let path_arg = path.display().to_string();
+    let mut hook_args = vec![path_arg.as_str()];
+    hook_args.extend(source);
+    hooks::run(repo, "prepare-commit-msg", &hook_args)?;
+    edit(repo, &path)?;
+    let edited = fs::read_to_string(&path).map_err(|e| Error::from_str(&e.to_string()))?;
+
+    Ok(clean(&edited, &cleanup, comment))
+}
+
+/// Clean up a message the way the commit will store it. git's `default`
+/// cleanup strips comments only from messages that went through the editor.
+pub fn clean_message(repo: &Repository, message: &str, edited: bool) -> Result<String, Error> {
+    let (mode, comment) = cleanup(repo)?;
+    let mode = match mode.as_str() {
+        "default" if edited => "strip",
+        "default" => "whitespace",
+        mode => mode,
+    };
+    Ok(clean(message, mode, comment))
+}
+
+/// Tidy an edited message like `git commit --cleanup=<mode>`.
+fn clean(message: &str, mode: &str, comment: char) -> String {
+    if mode == "verbatim" {
+        return message.to_string();
+    }
+
+    let scissors = format!("{} {}", comment, SCISSORS);
+    let mut lines: Vec<&str> = Vec::new();
+    for line in message.lines() {
+        if mode == "scissors" && line == scissors {
+            break;
+        }
+        if matches!(mode, "strip" | "default") && line.starts_with(comment) {
+            continue;
+        }
+        let line = line.trim_end();
+        // collapse runs of blank lines into one
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
+    }
+
+    lines.join("\n")
+}
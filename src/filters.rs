@@ -0,0 +1,154 @@
+use git2::{AttrCheckFlags, Error, Index, IndexEntry, IndexTime, Oid, Repository};
+use std::fs::{self, Metadata};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// `index.add_path`, plus the external clean filter from `.gitattributes`
+/// (`filter.<driver>.clean`, e.g. Git LFS) that libgit2 doesn't run, so the
+/// index gets the same blob `git add` would write. Only the cleaned blob is
+/// written, never the file itself.
+pub fn add_path(repo: &Repository, index: &mut Index, path: &Path) -> Result<(), Error> {
+    let clean = match clean_command(repo, path)? {
+        Some(clean) => clean,
+        None => return index.add_path(path),
+    };
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| Error::from_str("clean filters need a working tree"))?;
+    let full_path = workdir.join(path);
+
+    let unreadable =
+        |e: std::io::Error| Error::from_str(&format!("unable to read {}: {}", path.display(), e));
+    let metadata = fs::symlink_metadata(&full_path).map_err(unreadable)?;
+    let contents = fs::read(&full_path).map_err(unreadable)?;
+    let cleaned = run(workdir, &clean, path, contents)?;
+
+    // the stat data of the working tree file, so it doesn't look modified
+    let mut entry = entry(path, &metadata, repo.blob(&cleaned)?);
+    if !repo.config()?.get_bool("core.fileMode").unwrap_or(true) {
+        if let Some(existing) = index.get_path(path, 0) {
+            entry.mode = existing.mode;
+        }
+    }
+    index.add(&entry)
+}
+
+/// The clean command `.gitattributes` and config give a regular file, if any.
+pub fn clean_command(repo: &Repository, path: &Path) -> Result<Option<String>, Error> {
+    let driver = match repo.get_attr(path, "filter", AttrCheckFlags::FILE_THEN_INDEX)? {
+        Some(driver) if !driver.is_empty() => driver.to_string(),
+        _ => return Ok(None),
+    };
+    let workdir = match repo.workdir() {
+        Some(workdir) => workdir,
+        None => return Ok(None),
+    };
+    if !fs::symlink_metadata(workdir.join(path)).is_ok_and(|metadata| metadata.is_file()) {
+        return Ok(None);
+    }
+
+    let config = repo.config()?;
+    match config.get_string(&format!("filter.{}.clean", driver)) {
+        Ok(clean) => Ok(Some(clean)),
+        Err(_)
+            if config
+                .get_bool(&format!("filter.{}.required", driver))
+                .unwrap_or(false) =>
+        {
+            Err(Error::from_str(&format!(
+                "{} needs filter '{}' but filter.{}.clean is not set",
+                path.display(),
+                driver,
+                driver
+            )))
+        }
+        // like git, an unconfigured filter is a no-op
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(unix)]
+fn entry(path: &Path, metadata: &Metadata, id: Oid) -> IndexEntry {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+
+    IndexEntry {
+        ctime: IndexTime::new(metadata.ctime() as i32, metadata.ctime_nsec() as u32),
+        mtime: IndexTime::new(metadata.mtime() as i32, metadata.mtime_nsec() as u32),
+        dev: metadata.dev() as u32,
+        ino: metadata.ino() as u32,
+        mode: if metadata.mode() & 0o111 != 0 {
+            0o100755
+        } else {
+            0o100644
+        },
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        file_size: metadata.len() as u32,
+        id,
+        flags: 0,
+        flags_extended: 0,
+        path: path.as_os_str().as_bytes().to_vec(),
+    }
+}
+
+#[cfg(not(unix))]
+fn entry(path: &Path, metadata: &Metadata, id: Oid) -> IndexEntry {
+    let time = |time: std::io::Result<std::time::SystemTime>| {
+        let since_epoch = time
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        IndexTime::new(since_epoch.as_secs() as i32, since_epoch.subsec_nanos())
+    };
+
+    IndexEntry {
+        ctime: time(metadata.created()),
+        mtime: time(metadata.modified()),
+        dev: 0,
+        ino: 0,
+        mode: 0o100644,
+        uid: 0,
+        gid: 0,
+        file_size: metadata.len() as u32,
+        id,
+        flags: 0,
+        flags_extended: 0,
+        path: path.to_string_lossy().replace('\\', "/").into_bytes(),
+    }
+}
+
+/// Run a clean command with `%f` replaced by the path, as git does.
+fn run(workdir: &Path, command: &str, path: &Path, contents: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let quoted = format!("'{}'", path.display().to_string().replace('\'', "'\\''"));
+    let command = command.replace("%f", &quoted);
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .current_dir(workdir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::from_str(&format!("unable to run '{}': {}", command, e)))?;
+
+    // write from another thread so a filter streaming its output can't deadlock
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(&contents));
+    let output = child
+        .wait_with_output()
+        .map_err(|e| Error::from_str(&format!("'{}' failed: {}", command, e)))?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        return Err(Error::from_str(&format!(
+            "clean filter '{}' failed for {}",
+            command,
+            path.display()
+        )));
+    }
+
+    Ok(output.stdout)
+}
@@ -0,0 +1,52 @@
+use git2::{Error, Repository};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Run a client-side hook from `core.hooksPath` (or `.git/hooks`) with git's
+/// working directory and environment. Missing or non-executable hooks are
+/// skipped, a non-zero exit is an error.
+pub fn run(repo: &Repository, name: &str, args: &[&str]) -> Result<(), Error> {
+    let hook = match dir(repo)?.map(|dir| dir.join(name)) {
+        Some(hook) if is_executable(&hook) => hook,
+        _ => return Ok(()),
+    };
+
+    let workdir = repo.workdir().unwrap_or_else(|| repo.path());
+    let status = Command::new(&hook)
+        .args(args)
+        .current_dir(workdir)
+        .env("GIT_DIR", repo.path())
+        .env("GIT_INDEX_FILE", repo.path().join("index"))
+        .env("GIT_EDITOR", ":")
+        .status()
+        .map_err(|e| Error::from_str(&format!("unable to run {} hook: {}", name, e)))?;
+
+    if !status.success() {
+        return Err(Error::from_str(&format!("{} hook failed", name)));
+    }
+
+    Ok(())
+}
+
+fn dir(repo: &Repository) -> Result<Option<PathBuf>, Error> {
+    match repo.config()?.get_path("core.hooksPath") {
+        Ok(path) if path.is_relative() => Ok(repo.workdir().map(|workdir| workdir.join(path))),
+        Ok(path) => Ok(Some(path)),
+        Err(_) => Ok(Some(repo.path().join("hooks"))),
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
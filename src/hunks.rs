@@ -0,0 +1,398 @@
+use crate::pathspec::Filter;
+use crate::{ask, editor, StagedFile};
+use colored::*;
+use git2::{ApplyLocation, Delta, Diff, Error, Patch, Repository};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+
+/// One hunk of a file's unstaged changes, as `(origin, content)` lines.
+#[derive(Clone)]
+struct Hunk {
+    old_start: u32,
+    lines: Vec<(char, Vec<u8>)>,
+}
+
+impl Hunk {
+    fn count(&self, origins: &[char]) -> u32 {
+        self.lines
+            .iter()
+            .filter(|(origin, _)| origins.contains(origin))
+            .count() as u32
+    }
+
+    fn old_lines(&self) -> u32 {
+        self.count(&[' ', '-'])
+    }
+
+    fn new_lines(&self) -> u32 {
+        self.count(&[' ', '+'])
+    }
+
+    fn header(&self, new_start: u32) -> String {
+        format!(
+            "@@ -{},{} +{},{} @@",
+            self.old_start,
+            self.old_lines(),
+            new_start,
+            self.new_lines()
+        )
+    }
+
+    /// The hunk as patch text, with a header placing it at `new_start`.
+    fn text(&self, new_start: u32) -> Vec<u8> {
+        let mut text = format!("{}\n", self.header(new_start)).into_bytes();
+        for (origin, content) in &self.lines {
+            text.push(*origin as u8);
+            text.extend_from_slice(content);
+            if !content.ends_with(b"\n") {
+                text.extend_from_slice(b"\n\\ No newline at end of file\n");
+            }
+        }
+        text
+    }
+
+    fn print(&self) {
+        println!("{}", self.header(self.old_start).cyan());
+        for (origin, content) in &self.lines {
+            let line = format!("{}{}", origin, String::from_utf8_lossy(content));
+            let line = line.trim_end_matches('\n');
+            match origin {
+                '+' => println!("{}", line.green()),
+                '-' => println!("{}", line.red()),
+                _ => println!("{}", line),
+            }
+        }
+    }
+
+    /// Split at the context between separate runs of changes, like
+    /// `git add -p`. Context between two runs goes to both halves.
+    fn split(&self) -> Vec<Hunk> {
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        let mut i = 0;
+        while i < self.lines.len() {
+            if self.lines[i].0 == ' ' {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < self.lines.len() && self.lines[i].0 != ' ' {
+                i += 1;
+            }
+            runs.push((start, i));
+        }
+        if runs.len() < 2 {
+            return vec![self.clone()];
+        }
+
+        (0..runs.len())
+            .map(|run| {
+                let start = if run == 0 { 0 } else { runs[run - 1].1 };
+                let end = runs.get(run + 1).map_or(self.lines.len(), |next| next.0);
+                let skipped = Hunk {
+                    old_start: 0,
+                    lines: self.lines[..start].to_vec(),
+                };
+                Hunk {
+                    old_start: self.old_start + skipped.old_lines(),
+                    lines: self.lines[start..end].to_vec(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Walk the unstaged hunks of every modified file matching `filter`, asking
+/// which to stage, and apply the chosen ones to the index.
+pub fn stage(repo: &Repository, filter: &Filter) -> Result<Vec<StagedFile>, Error> {
+    let diff = repo.diff_index_to_workdir(None, None)?;
+    let mut files = Vec::new();
+
+    'files: for idx in 0..diff.deltas().len() {
+        let delta = diff.get_delta(idx).unwrap();
+        let path = match delta.new_file().path() {
+            Some(path) if delta.status() == Delta::Modified && filter.matches(path) => path,
+            _ => continue,
+        };
+        let patch = match Patch::from_diff(&diff, idx)? {
+            Some(patch) => patch,
+            None => continue, // binary
+        };
+
+        let mut queue = hunks(&patch)?;
+        let mut chosen: Vec<Hunk> = Vec::new();
+        let mut quit = false;
+
+        println!(
+            "{}",
+            format!("diff --git a/{0} b/{0}", path.display()).bold()
+        );
+        while let Some(hunk) = queue.pop_front() {
+            hunk.print();
+            loop {
+                match ask("Stage this hunk [y,n,s,e,q,?]? ")
+                    .map(|answer| answer.to_lowercase())
+                    .as_deref()
+                {
+                    Some("y") => chosen.push(hunk),
+                    Some("n") => {}
+                    Some("s") => {
+                        let parts = hunk.split();
+                        if parts.len() < 2 {
+                            println!("{}", "Sorry, cannot split this hunk".yellow());
+                            continue;
+                        }
+                        println!("Split into {} hunks.", parts.len());
+                        for part in parts.into_iter().rev() {
+                            queue.push_front(part);
+                        }
+                    }
+                    Some("e") => match edit(repo, &hunk)? {
+                        Some(edited) => chosen.push(edited),
+                        None => {
+                            println!("{}", "The edited hunk has no changes".yellow());
+                            continue;
+                        }
+                    },
+                    Some("q") | None => quit = true,
+                    _ => {
+                        println!(
+                            "y - stage this hunk\nn - do not stage this hunk\ns - split this hunk\ne - edit this hunk\nq - quit, staging what was chosen so far"
+                        );
+                        continue;
+                    }
+                }
+                break;
+            }
+            if quit {
+                break;
+            }
+        }
+
+        if !chosen.is_empty() {
+            apply(repo, path, &mut chosen)?;
+            files.push(StagedFile {
+                path: path.display().to_string(),
+                old_path: None,
+                status: git2::Status::INDEX_MODIFIED,
+            });
+        }
+        if quit {
+            break 'files;
+        }
+    }
+
+    Ok(files)
+}
+
+fn hunks(patch: &Patch) -> Result<VecDeque<Hunk>, Error> {
+    let mut hunks = VecDeque::new();
+
+    for hunk_idx in 0..patch.num_hunks() {
+        let (hunk, _) = patch.hunk(hunk_idx)?;
+        let mut lines = Vec::new();
+        for line_idx in 0..patch.num_lines_in_hunk(hunk_idx)? {
+            let line = patch.line_in_hunk(hunk_idx, line_idx)?;
+            // missing newlines show up as content without a trailing \n
+            if matches!(line.origin(), ' ' | '+' | '-') {
+                lines.push((line.origin(), line.content().to_vec()));
+            }
+        }
+        hunks.push_back(Hunk {
+            old_start: hunk.old_start(),
+            lines,
+        });
+    }
+
+    Ok(hunks)
+}
+
+/// Let the user edit the hunk in their editor. `None` if nothing is left.
+fn edit(repo: &Repository, hunk: &Hunk) -> Result<Option<Hunk>, Error> {
+    let path = repo.path().join("ADD_EDIT.patch");
+    let mut text = b"# Manual hunk edit mode\n\
+        # To remove '-' lines, make them ' ' lines (context).\n\
+        # To remove '+' lines, delete them.\n\
+        # Lines starting with # will be removed.\n"
+        .to_vec();
+    text.extend(hunk.text(hunk.old_start));
+
+    fs::write(&path, &text).map_err(|e| Error::from_str(&e.to_string()))?;
+    editor::edit(repo, &path)?;
+    let edited = fs::read(&path).map_err(|e| Error::from_str(&e.to_string()))?;
+    let _ = fs::remove_file(&path);
+
+    Ok(parse_edited(&edited, hunk.old_start))
+}
+
+/// The hunk in an edited `ADD_EDIT.patch`, `None` if it has no changes left.
+fn parse_edited(edited: &[u8], old_start: u32) -> Option<Hunk> {
+    let mut lines: Vec<(char, Vec<u8>)> = Vec::new();
+    for line in edited.split_inclusive(|&byte| byte == b'\n') {
+        match line.first() {
+            Some(b'#') | Some(b'@') => {}
+            Some(b'\\') => {
+                if let Some((_, content)) = lines.last_mut() {
+                    content.pop(); // no newline at end of file
+                }
+            }
+            Some(&origin @ (b' ' | b'+' | b'-')) => {
+                lines.push((origin as char, line[1..].to_vec()))
+            }
+            // editors may strip the space of empty context lines
+            Some(b'\n') => lines.push((' ', b"\n".to_vec())),
+            _ => {}
+        }
+    }
+
+    let edited = Hunk { old_start, lines };
+    if edited.count(&['+', '-']) == 0 {
+        return None;
+    }
+    Some(edited)
+}
+
+/// Apply the chosen hunks of one file to the index.
+fn apply(repo: &Repository, path: &Path, chosen: &mut [Hunk]) -> Result<(), Error> {
+    let diff: Diff = Diff::from_buffer(&patch_text(path, chosen))?;
+    repo.apply(&diff, ApplyLocation::Index, None).map_err(|e| {
+        Error::from_str(&format!(
+            "{} did not apply: {}",
+            path.display(),
+            e.message()
+        ))
+    })
+}
+
+/// The chosen hunks of one file as a patch against the index.
+fn patch_text(path: &Path, chosen: &mut [Hunk]) -> Vec<u8> {
+    chosen.sort_by_key(|hunk| hunk.old_start);
+
+    let mut patch = format!(
+        "diff --git a/{0} b/{0}\n--- a/{0}\n+++ b/{0}\n",
+        path.display()
+    )
+    .into_bytes();
+    // hunks that were skipped don't shift the ones after them
+    let mut offset: i64 = 0;
+    for hunk in chosen.iter() {
+        let new_start = (hunk.old_start as i64 + offset).max(0) as u32;
+        patch.extend(hunk.text(new_start));
+        offset += hunk.new_lines() as i64 - hunk.old_lines() as i64;
+    }
+    patch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A hunk from `(origin, content)` lines, newlines added.
+    fn hunk(old_start: u32, lines: &[(char, &str)]) -> Hunk {
+        Hunk {
+            old_start,
+            lines: lines
+                .iter()
+                .map(|(origin, content)| (*origin, format!("{}\n", content).into_bytes()))
+                .collect(),
+        }
+    }
+
+    fn origins(hunk: &Hunk) -> String {
+        hunk.lines.iter().map(|(origin, _)| origin).collect()
+    }
+
+    #[test]
+    fn split_single_run() {
+        let whole = hunk(3, &[(' ', "a"), ('-', "b"), ('+', "B"), (' ', "c")]);
+        let parts = whole.split();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(origins(&parts[0]), " -+ ");
+    }
+
+    #[test]
+    fn split_runs() {
+        let whole = hunk(
+            10,
+            &[
+                (' ', "a"),
+                ('-', "b"),
+                (' ', "c"),
+                (' ', "d"),
+                ('+', "e"),
+                ('+', "f"),
+                (' ', "g"),
+                ('-', "h"),
+                ('+', "H"),
+                (' ', "i"),
+            ],
+        );
+        let parts = whole.split();
+        assert_eq!(parts.len(), 3);
+        // the context between two runs goes to both of them
+        assert_eq!(origins(&parts[0]), " -  ");
+        assert_eq!(origins(&parts[1]), "  ++ ");
+        assert_eq!(origins(&parts[2]), " -+ ");
+        // each part starts after the old lines before it
+        assert_eq!(parts[0].old_start, 10);
+        assert_eq!(parts[1].old_start, 12);
+        assert_eq!(parts[2].old_start, 14);
+        assert_eq!(parts[1].header(12), "@@ -12,3 +12,5 @@");
+    }
+
+    #[test]
+    fn split_leading_run() {
+        let whole = hunk(1, &[('+', "a"), (' ', "b"), ('-', "c")]);
+        let parts = whole.split();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(origins(&parts[0]), "+ ");
+        assert_eq!(origins(&parts[1]), " -");
+        assert_eq!(parts[1].old_start, 1);
+    }
+
+    #[test]
+    fn text_without_newline() {
+        let mut last = hunk(5, &[(' ', "a"), ('-', "b"), ('+', "B")]);
+        last.lines[2].1.pop();
+        assert_eq!(
+            String::from_utf8(last.text(5)).unwrap(),
+            "@@ -5,2 +5,2 @@\n a\n-b\n+B\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn patch_after_skipped_hunks() {
+        // only the chosen hunk before it shifts the second one, by the two lines
+        // it adds; the hunks skipped in between are not in the patch
+        let mut chosen = vec![
+            hunk(40, &[(' ', "x"), ('-', "y"), (' ', "z")]),
+            hunk(2, &[(' ', "a"), ('+', "b"), ('+', "c"), (' ', "d")]),
+        ];
+        let patch = patch_text(Path::new("f.txt"), &mut chosen);
+        let patch = String::from_utf8(patch).unwrap();
+        assert_eq!(
+            patch,
+            "diff --git a/f.txt b/f.txt\n--- a/f.txt\n+++ b/f.txt\n\
+             @@ -2,2 +2,4 @@\n a\n+b\n+c\n d\n\
+             @@ -40,3 +42,2 @@\n x\n-y\n z\n"
+        );
+    }
+
+    #[test]
+    fn parse_edited_hunk() {
+        let edited = b"# Manual hunk edit mode\n\
+            @@ -3,3 +3,3 @@\n a\n-b\n+B\n\n+C\n\\ No newline at end of file\n";
+        let hunk = parse_edited(edited, 3).unwrap();
+        assert_eq!(hunk.old_start, 3);
+        assert_eq!(origins(&hunk), " -+ +");
+        // an empty line is context the editor stripped the space from
+        assert_eq!(hunk.lines[3].1, b"\n");
+        assert_eq!(hunk.lines[4].1, b"C");
+    }
+
+    #[test]
+    fn parse_edited_without_changes() {
+        let edited = b"@@ -3,2 +3,2 @@\n a\n b\n# -c\n";
+        assert!(parse_edited(edited, 3).is_none());
+    }
+}
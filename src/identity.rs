@@ -0,0 +1,232 @@
+use git2::{Config, Error, Repository, Signature, Time};
+use regex::Regex;
+use std::env;
+use std::sync::LazyLock;
+
+/// Author and committer signatures resolved with git's precedence:
+/// `GIT_AUTHOR_*`/`GIT_COMMITTER_*` env vars (including `_DATE`), then `author.*`/`committer.*`
+/// config, then `user.*` config, then `$EMAIL` for the address. Config comes
+/// from `repo.config()`, so `.git/config`, worktree config and `includeIf`
+/// rules all apply.
+pub fn signatures(repo: &Repository) -> Result<(Signature<'static>, Signature<'static>), Error> {
+    let config = repo.config()?.snapshot()?;
+    Ok((
+        signature(&config, "author")?,
+        signature(&config, "committer")?,
+    ))
+}
+
+fn signature(config: &Config, role: &str) -> Result<Signature<'static>, Error> {
+    let env_role = role.to_uppercase();
+    let lookup = |field: &str| {
+        env::var(format!("GIT_{}_{}", env_role, field.to_uppercase()))
+            .ok()
+            .or_else(|| config.get_string(&format!("{}.{}", role, field)).ok())
+            .or_else(|| config.get_string(&format!("user.{}", field)).ok())
+            .filter(|value| !value.trim().is_empty())
+    };
+
+    let name = lookup("name");
+    let email = lookup("email").or_else(|| env::var("EMAIL").ok());
+
+    let (name, email) = match (name, email) {
+        (Some(name), Some(email)) => (name, email),
+        _ => {
+            return Err(Error::from_str(&format!(
+                "{} identity unknown, set user.name and user.email",
+                role
+            )))
+        }
+    };
+
+    let now = Signature::now(&name, &email)?;
+    let variable = format!("GIT_{}_DATE", env_role);
+    match env::var(&variable) {
+        Ok(date) if !date.trim().is_empty() => {
+            // dates without a zone are local time, like in git
+            let time = parse_date(date.trim(), now.when().offset_minutes()).ok_or_else(|| {
+                Error::from_str(&format!("invalid date format '{}' in {}", date, variable))
+            })?;
+            Signature::new(&name, &email, &time)
+        }
+        _ => Ok(now),
+    }
+}
+
+// Compiled once, signatures are resolved for both the author and committer
+static RAW: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^@?(\d+)(?:\s+([+-]\d{4}))?$").unwrap());
+static RFC2822: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?:[A-Za-z]{3},\s*)?(\d{1,2})\s+([A-Za-z]{3})\s+(\d{4})\s+(\d{2}):(\d{2})(?::(\d{2}))?(?:\s*(\S+))?$",
+    )
+    .unwrap()
+});
+static ISO8601: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\d{4})-(\d{2})-(\d{2})[T ](\d{2}):(\d{2})(?::(\d{2})(?:\.\d+)?)?(?:\s*(\S+))?$")
+        .unwrap()
+});
+
+/// A date in one of the formats git takes in `GIT_*_DATE`: its own
+/// `1112911993 +0200` (optionally `@`-prefixed), RFC 2822
+/// `Thu, 07 Apr 2005 22:13:13 +0200` or ISO 8601 `2005-04-07T22:13:13+02:00`.
+fn parse_date(date: &str, local_offset: i32) -> Option<Time> {
+    if let Some(captures) = RAW.captures(date) {
+        let seconds = captures[1].parse().ok()?;
+        let offset = match captures.get(2) {
+            Some(zone) => parse_zone(zone.as_str())?,
+            None => 0,
+        };
+        return Some(Time::new(seconds, offset));
+    }
+
+    // both put the time at 4-6 and the zone at 7
+    let (year, month, day, captures) = if let Some(captures) = RFC2822.captures(date) {
+        const MONTHS: [&str; 12] = [
+            "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+        ];
+        let month = MONTHS
+            .iter()
+            .position(|month| captures[2].eq_ignore_ascii_case(month))? as i64
+            + 1;
+        let (year, day) = (captures[3].parse().ok()?, captures[1].parse().ok()?);
+        (year, month, day, captures)
+    } else if let Some(captures) = ISO8601.captures(date) {
+        let year = captures[1].parse().ok()?;
+        let month = captures[2].parse().ok()?;
+        let day = captures[3].parse().ok()?;
+        (year, month, day, captures)
+    } else {
+        return None;
+    };
+    let number = |idx: usize| -> Option<i64> {
+        captures
+            .get(idx)
+            .map_or(Some(0), |value| value.as_str().parse().ok())
+    };
+    let (hour, minute, second) = (number(4)?, number(5)?, number(6)?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let offset = match captures.get(7) {
+        Some(zone) => parse_zone(zone.as_str())?,
+        None => local_offset,
+    };
+
+    let seconds = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second
+        - offset as i64 * 60;
+    Some(Time::new(seconds, offset))
+}
+
+/// Minutes east of UTC from `+0200`, `+02:00`, `Z`, `UT` or `GMT`.
+fn parse_zone(zone: &str) -> Option<i32> {
+    if matches!(zone, "Z" | "z" | "UT" | "UTC" | "GMT") {
+        return Some(0);
+    }
+    let sign = match zone.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = zone[1..].chars().filter(|&c| c != ':').collect();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 60 + minutes))
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse with a local zone of +01:00, as seconds and offset minutes.
+    fn parse(date: &str) -> Option<(i64, i32)> {
+        parse_date(date, 60).map(|time| (time.seconds(), time.offset_minutes()))
+    }
+
+    #[test]
+    fn raw() {
+        assert_eq!(parse("1112911993 +0200"), Some((1112911993, 120)));
+        assert_eq!(parse("1112911993 -0130"), Some((1112911993, -90)));
+        // no zone is UTC, the seconds are already absolute
+        assert_eq!(parse("1112911993"), Some((1112911993, 0)));
+    }
+
+    #[test]
+    fn raw_with_at() {
+        assert_eq!(parse("@1112911993 +0200"), Some((1112911993, 120)));
+        assert_eq!(parse("@1112911993"), Some((1112911993, 0)));
+    }
+
+    #[test]
+    fn rfc2822() {
+        assert_eq!(
+            parse("Thu, 07 Apr 2005 22:13:13 +0200"),
+            Some((1112904793, 120))
+        );
+        assert_eq!(parse("7 Apr 2005 22:13:13 +0200"), Some((1112904793, 120)));
+        assert_eq!(
+            parse("Thu, 07 Apr 2005 22:13:13 GMT"),
+            Some((1112911993, 0))
+        );
+    }
+
+    #[test]
+    fn iso8601_with_zone() {
+        assert_eq!(parse("2005-04-07T22:13:13+02:00"), Some((1112904793, 120)));
+        assert_eq!(parse("2005-04-07T22:13:13+0200"), Some((1112904793, 120)));
+        assert_eq!(parse("2005-04-07 22:13:13 -0130"), Some((1112917393, -90)));
+        assert_eq!(parse("2005-04-07T22:13:13Z"), Some((1112911993, 0)));
+        assert_eq!(parse("2005-04-07T22:13:13.250Z"), Some((1112911993, 0)));
+    }
+
+    #[test]
+    fn iso8601_without_zone_is_local() {
+        assert_eq!(parse("2005-04-07T22:13:13"), Some((1112908393, 60)));
+        assert_eq!(parse("2005-04-07 22:13"), Some((1112908380, 60)));
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("garbage"), None);
+        assert_eq!(parse("yesterday"), None);
+        assert_eq!(parse("1112911993 +02"), None);
+        assert_eq!(parse("2005-13-07T22:13:13Z"), None);
+        assert_eq!(parse("2005-04-07T25:13:13Z"), None);
+        assert_eq!(parse("Thu, 07 Foo 2005 22:13:13 +0200"), None);
+        assert_eq!(parse("2005-04-07T22:13:13+2"), None);
+    }
+
+    #[test]
+    fn zones() {
+        assert_eq!(parse_zone("Z"), Some(0));
+        assert_eq!(parse_zone("+02:00"), Some(120));
+        assert_eq!(parse_zone("-0530"), Some(-330));
+        assert_eq!(parse_zone("0200"), None);
+    }
+
+    #[test]
+    fn days() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+    }
+}
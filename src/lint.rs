@@ -0,0 +1,137 @@
+use git2::{Config, Error, Repository};
+use regex::Regex;
+
+/// The file with the team's message rules, committed at the top of the repo.
+pub const FILE: &str = ".qcconfig";
+
+/// Words that look like they aren't imperative but are.
+const IMPERATIVE_EXCEPTIONS: &[&str] = &[
+    "bring", "embed", "feed", "need", "proceed", "seed", "shed", "speed", "spring", "string",
+    "succeed",
+];
+
+/// A rule the message breaks.
+pub struct Problem {
+    pub reason: String,
+    /// Whether it stops the commit rather than just warning
+    pub block: bool,
+}
+
+/// Check a commit message against the `[lint]` rules in `.qcconfig`:
+///
+/// ```text
+/// [lint]
+///     maxSubjectLength = 72
+///     imperative = true
+///     trailingPeriod = false
+///     ticketPattern = "[A-Z]+-[0-9]+"
+///     forbiddenWord = wip
+///     severity = warn
+/// ```
+///
+/// Rules that aren't set aren't checked. `severity` is `error` (the default)
+/// or `warn`.
+pub fn check(repo: &Repository, message: &str) -> Result<Vec<Problem>, Error> {
+    let path = match repo.workdir() {
+        Some(workdir) if workdir.join(FILE).is_file() => workdir.join(FILE),
+        _ => return Ok(Vec::new()),
+    };
+    let config = Config::open(&path)?;
+    let block = match config.get_string("lint.severity") {
+        Ok(severity) if severity == "warn" => false,
+        Ok(severity) if severity == "error" => true,
+        Ok(severity) => {
+            return Err(Error::from_str(&format!(
+                "lint.severity in {} must be 'error' or 'warn', not '{}'",
+                FILE, severity
+            )))
+        }
+        Err(_) => true,
+    };
+
+    let subject = message.lines().next().unwrap_or_default();
+    let mut reasons = Vec::new();
+
+    if let Some(max) = setting(config.get_i64("lint.maxSubjectLength"))? {
+        let length = subject.chars().count() as i64;
+        if length > max {
+            reasons.push(format!(
+                "subject is {} characters, over lint.maxSubjectLength of {}",
+                length, max
+            ));
+        }
+    }
+
+    if setting(config.get_bool("lint.imperative"))? == Some(true) {
+        if let Some(word) = not_imperative(subject) {
+            reasons.push(format!(
+                "subject should be imperative, '{}' reads like a description",
+                word
+            ));
+        }
+    }
+
+    if setting(config.get_bool("lint.trailingPeriod"))? == Some(false) && subject.ends_with('.') {
+        reasons.push("subject ends with a period".to_string());
+    }
+
+    if let Some(pattern) = setting(config.get_string("lint.ticketPattern"))? {
+        let regex = Regex::new(&pattern).map_err(|e| {
+            Error::from_str(&format!(
+                "invalid lint.ticketPattern '{}' in {}: {}",
+                pattern, FILE, e
+            ))
+        })?;
+        if !regex.is_match(message) {
+            reasons.push(format!("no ticket reference matching '{}'", pattern));
+        }
+    }
+
+    if let Ok(entries) = config.multivar("lint.forbiddenWord", None) {
+        for entry in &entries {
+            let word = entry?.value().unwrap_or_default().to_string();
+            let regex = Regex::new(&format!(r"(?i)\b{}\b", regex::escape(&word))).unwrap();
+            if regex.is_match(message) {
+                reasons.push(format!("contains forbidden word '{}'", word));
+            }
+        }
+    }
+
+    Ok(reasons
+        .into_iter()
+        .map(|reason| Problem { reason, block })
+        .collect())
+}
+
+/// A set value, `None` for a missing one and an error for a malformed one.
+fn setting<T>(value: Result<T, Error>) -> Result<Option<T>, Error> {
+    match value {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(Error::from_str(&format!("{} in {}", e.message(), FILE))),
+    }
+}
+
+/// The first word of the subject if it looks past tense ("added"),
+/// progressive ("adding") or third person ("adds"). Any Conventional Commits
+/// `type(scope):` prefix is skipped.
+fn not_imperative(subject: &str) -> Option<&str> {
+    let prefix = Regex::new(r"^[a-zA-Z]+(\([^()]*\))?!?:\s*").unwrap();
+    let rest = match prefix.find(subject) {
+        Some(found) => &subject[found.end()..],
+        None => subject,
+    };
+    let word = rest.split_whitespace().next()?;
+    let lower = word.to_lowercase();
+
+    if IMPERATIVE_EXCEPTIONS.contains(&lower.as_str()) || lower.len() < 4 {
+        return None;
+    }
+    let described = lower.ends_with("ed")
+        || lower.ends_with("ing")
+        || (lower.ends_with('s')
+            && !lower.ends_with("ss")
+            && !lower.ends_with("us")
+            && !lower.ends_with("is"));
+    described.then_some(word)
+}
@@ -1,100 +1,499 @@
+mod cli;
+mod conventional;
+mod diffstat;
+mod editor;
+mod filters;
+mod hooks;
+mod hunks;
+mod identity;
+mod lint;
+mod pathspec;
+mod picker;
+mod preview;
+mod push;
+mod secrets;
+mod sign;
+mod sizes;
+mod snapshot;
+mod state;
+
+use clap::Parser;
+use cli::Args;
 use colored::*;
-use git2::{Config, ErrorCode, Repository, Signature, StatusOptions};
-use std::env;
+use git2::{
+    Commit, Delta, Diff, DiffFindOptions, DiffOptions, ErrorCode, Index, Repository, StatusOptions,
+    Tree,
+};
+use pathspec::Filter;
+use snapshot::IndexSnapshot;
+use std::fs;
 use std::io::{self, stdout, Write};
 use std::path::Path;
-use std::process::{Command, Stdio};
 
-fn stage(repo: &Repository) -> Result<Vec<(String, git2::Status)>, git2::Error> {
+/// A change picked up by `stage`, classified by its `INDEX_*` status.
+struct StagedFile {
+    path: String,
+    /// Where a renamed file came from
+    old_path: Option<String>,
+    status: git2::Status,
+}
+
+impl StagedFile {
+    /// The one-letter marker and color the file is listed with.
+    fn marker(&self) -> (&'static str, Color) {
+        match self.status {
+            git2::Status::INDEX_NEW => ("+", Color::Green),
+            git2::Status::INDEX_DELETED => ("-", Color::Red),
+            git2::Status::INDEX_RENAMED => ("R", Color::Blue),
+            git2::Status::INDEX_TYPECHANGE => ("T", Color::Magenta),
+            _ => ("M", Color::Yellow),
+        }
+    }
+
+    /// The marker and path, with the old path of a rename.
+    fn label(&self) -> String {
+        let (marker, _) = self.marker();
+        match &self.old_path {
+            Some(old_path) => format!("{} {} -> {}", marker, old_path, self.path),
+            None => format!("{} {}", marker, self.path),
+        }
+    }
+}
+
+/// Stage every change in the working tree matching `filter`. With `dry_run`
+/// the same file list is returned but the index is left alone.
+fn stage(
+    repo: &Repository,
+    filter: &Filter,
+    dry_run: bool,
+) -> Result<Vec<StagedFile>, git2::Error> {
     let mut index = repo.index()?;
+    // a dry run only needs the file list, adding would hash the files into
+    // the object database
+    let add = |index: &mut Index, path: &Path| {
+        if dry_run {
+            Ok(())
+        } else {
+            filters::add_path(repo, index, path)
+        }
+    };
 
     let mut options = StatusOptions::new();
-    options.include_untracked(true).recurse_untracked_dirs(true);
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .renames_head_to_index(true)
+        .renames_index_to_workdir(true);
 
-    let mut files: Vec<(String, git2::Status)> = Vec::new();
+    let mut files: Vec<StagedFile> = Vec::new();
+    let mut conflicts: Vec<String> = Vec::new();
 
     for entry in repo.statuses(Some(&mut options))?.iter() {
         let path = Path::new(std::str::from_utf8(entry.path_bytes()).unwrap());
+        let staged = entry.head_to_index();
+        let unstaged = entry.index_to_workdir();
+        // Renames report the old path, the new one is on the latest delta
+        let new_path = unstaged
+            .as_ref()
+            .or(staged.as_ref())
+            .and_then(|delta| delta.new_file().path())
+            .unwrap_or(path);
+        if !filter.matches(path) && !filter.matches(new_path) {
+            continue;
+        }
 
         match entry.status() {
+            status if status.contains(git2::Status::CONFLICTED) => {
+                let workdir_path = repo.workdir().unwrap_or_else(|| Path::new("")).join(path);
+                if state::has_conflict_markers(&workdir_path) {
+                    conflicts.push(path.display().to_string());
+                    continue;
+                }
+
+                // Staging a conflicted file marks it as resolved
+                if workdir_path.symlink_metadata().is_ok() {
+                    files.push(StagedFile {
+                        path: path.display().to_string(),
+                        old_path: None,
+                        status: git2::Status::INDEX_MODIFIED,
+                    });
+
+                    add(&mut index, path)?;
+                } else {
+                    files.push(StagedFile {
+                        path: path.display().to_string(),
+                        old_path: None,
+                        status: git2::Status::INDEX_DELETED,
+                    });
+
+                    index.remove_path(path)?;
+                }
+            }
+            status if status.intersects(git2::Status::INDEX_RENAMED | git2::Status::WT_RENAMED) => {
+                files.push(StagedFile {
+                    path: new_path.display().to_string(),
+                    old_path: Some(path.display().to_string()),
+                    status: git2::Status::INDEX_RENAMED,
+                });
+
+                if let Some(old) = unstaged
+                    .filter(|_| status.contains(git2::Status::WT_RENAMED))
+                    .and_then(|delta| delta.old_file().path())
+                {
+                    index.remove_path(old)?;
+                }
+                add(&mut index, new_path)?;
+            }
+            status
+                if status
+                    .intersects(git2::Status::INDEX_TYPECHANGE | git2::Status::WT_TYPECHANGE) =>
+            {
+                files.push(StagedFile {
+                    path: path.display().to_string(),
+                    old_path: None,
+                    status: git2::Status::INDEX_TYPECHANGE,
+                });
+
+                add(&mut index, path)?;
+            }
             status if status.intersects(git2::Status::INDEX_NEW | git2::Status::WT_NEW) => {
-                files.push((path.display().to_string(), git2::Status::INDEX_NEW));
+                files.push(StagedFile {
+                    path: path.display().to_string(),
+                    old_path: None,
+                    status: git2::Status::INDEX_NEW,
+                });
 
-                index.add_path(&path)?;
+                add(&mut index, path)?;
             }
             status
                 if status.intersects(git2::Status::INDEX_MODIFIED | git2::Status::WT_MODIFIED) =>
             {
-                files.push((path.display().to_string(), git2::Status::INDEX_MODIFIED));
+                files.push(StagedFile {
+                    path: path.display().to_string(),
+                    old_path: None,
+                    status: git2::Status::INDEX_MODIFIED,
+                });
 
-                index.add_path(&path)?;
+                add(&mut index, path)?;
             }
             status if status.intersects(git2::Status::INDEX_DELETED | git2::Status::WT_DELETED) => {
-                // test
-                files.push((path.display().to_string(), git2::Status::INDEX_DELETED));
+                files.push(StagedFile {
+                    path: path.display().to_string(),
+                    old_path: None,
+                    status: git2::Status::INDEX_DELETED,
+                });
 
-                index.remove_path(&path)?;
+                index.remove_path(path)?;
             }
             _ => continue,
         }
     }
 
-    index.write()?; // Write the changes to the index
+    if !conflicts.is_empty() {
+        index.read(true)?;
+        return Err(git2::Error::from_str(&format!(
+            "unresolved conflicts in {}",
+            conflicts.join(", ")
+        )));
+    }
+
+    if dry_run {
+        index.read(true)?; // Throw away the in-memory changes
+    } else {
+        index.write()?; // Write the changes to the index
+    }
 
     Ok(files)
 }
 
-fn commit(repo: &Repository, message: &str) -> Result<(), git2::Error> {
-    let mut index = repo.index()?;
-    let tree_oid = index.write_tree()?;
-    let tree = repo.find_tree(tree_oid)?;
+fn commit(
+    repo: &Repository,
+    message: &str,
+    verify: bool,
+    amend: Option<&Commit>,
+    edited: bool,
+    filter: &Filter,
+) -> Result<(), git2::Error> {
+    if verify {
+        hooks::run(repo, "pre-commit", &[])?;
+    }
+
+    // the message hooks edit .git/COMMIT_EDITMSG in place, like with git
+    let message_file = repo.path().join("COMMIT_EDITMSG");
+    let message_path = message_file.display().to_string();
+    fs::write(&message_file, format!("{}\n", message))
+        .map_err(|e| git2::Error::from_str(&format!("unable to write {}: {}", message_path, e)))?;
+    // an edited message had prepare-commit-msg run before the editor
+    match amend {
+        _ if edited => {}
+        Some(_) => hooks::run(
+            repo,
+            "prepare-commit-msg",
+            &[&message_path, "commit", "HEAD"],
+        )?,
+        None => hooks::run(repo, "prepare-commit-msg", &[&message_path, "message"])?,
+    }
+    if verify {
+        hooks::run(repo, "commit-msg", &[&message_path])?;
+    }
+    let message = fs::read_to_string(&message_file)
+        .map_err(|e| git2::Error::from_str(&format!("unable to read {}: {}", message_path, e)))?;
+    let message = editor::clean_message(repo, &message, edited)?;
+    let message = message.trim_end();
 
-    let config = Config::open_default()?;
-    let name = config.get_string("user.name")?;
-    let email = config.get_string("user.email")?;
+    // after pre-commit, which may have changed the index
+    let tree = commit_tree(repo, filter)?;
 
-    let signature = Signature::now(&name, &email)?;
+    let (mut author, committer) = identity::signatures(repo)?;
+    if let Some(picked) = state::cherry_pick_head(repo)? {
+        author = picked.author().to_owned();
+    }
 
-    let head = repo.head();
-    let head = match head {
-        Ok(head) => head,
-        Err(ref e) if e.code() == ErrorCode::UnbornBranch => {
-            repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &[])?;
-            return Ok(());
+    // an amended commit keeps its author and parents
+    let mut parents = match amend {
+        Some(old) => {
+            author = old.author().to_owned();
+            old.parents().collect()
         }
-        Err(e) => return Err(e),
+        None => match repo.head() {
+            Ok(head) => vec![repo.find_commit(head.target().unwrap())?],
+            Err(ref e) if e.code() == ErrorCode::UnbornBranch => vec![],
+            Err(e) => return Err(e),
+        },
     };
+    parents.extend(state::merge_heads(repo)?);
+    let parents: Vec<&Commit> = parents.iter().collect();
+
+    if sign::enabled(repo)? {
+        let buffer = repo.commit_create_buffer(&author, &committer, message, &tree, &parents)?;
+        let buffer = buffer
+            .as_str()
+            .ok_or_else(|| git2::Error::from_str("commit buffer is not valid UTF-8"))?;
+        let signature = sign::sign(repo, &committer, buffer)?;
+        let oid = repo.commit_signed(buffer, &signature, None)?;
+        let action = match amend {
+            Some(_) => "commit (amend)",
+            None if parents.is_empty() => "commit (initial)",
+            None => "commit",
+        };
+        update_head(repo, oid, message, action)?;
+    } else if let Some(old) = amend {
+        let oid = old.amend(
+            None,
+            Some(&author),
+            Some(&committer),
+            None,
+            Some(message),
+            Some(&tree),
+        )?;
+        update_head(repo, oid, message, "commit (amend)")?;
+    } else {
+        repo.commit(Some("HEAD"), &author, &committer, message, &tree, &parents)?;
+    }
 
-    let head_commit = repo.find_commit(head.target().unwrap())?;
+    // the merge, cherry-pick or revert is done now. HEAD has already moved,
+    // so from here on nothing may fail and have the index restored
+    if let Err(e) = repo.cleanup_state() {
+        eprintln!(
+            "{}",
+            format!(
+                "Committed, but unable to clear the merge state: {}",
+                e.message()
+            )
+            .yellow()
+        );
+    }
 
-    repo.commit(
-        Some("HEAD"),
-        &signature,
-        &signature,
-        message,
-        &tree,
-        &[&head_commit],
-    )?;
+    // like git, a failing post-commit hook doesn't undo the commit
+    let _ = hooks::run(repo, "post-commit", &[]);
 
     Ok(())
 }
 
-fn lines(repo: &Repository) -> Result<(usize, usize), git2::Error> {
+/// Point HEAD (or the branch it refers to) at a commit written without
+/// `repo.commit`, with the reflog entry git would write for `action`.
+fn update_head(
+    repo: &Repository,
+    oid: git2::Oid,
+    message: &str,
+    action: &str,
+) -> Result<(), git2::Error> {
+    let summary = message.lines().next().unwrap_or_default();
+    let log = format!("{}: {}", action, summary);
+
+    let head = repo.find_reference("HEAD")?;
+    match head.symbolic_target() {
+        Some(name) => repo.reference(name, oid, true, &log)?,
+        None => repo.reference("HEAD", oid, true, &log)?,
+    };
+
+    Ok(())
+}
+
+/// HEAD's tree, `None` on an unborn branch.
+fn head_tree(repo: &Repository) -> Result<Option<Tree<'_>>, git2::Error> {
+    match repo.head() {
+        Ok(head) => Ok(Some(head.peel_to_tree()?)),
+        Err(ref e) if e.code() == ErrorCode::UnbornBranch => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The tree to commit. That is the whole index, unless `filter` selects only
+/// some paths: then, like `git commit -- <paths>`, it is HEAD's tree with just
+/// the matching index entries, and changes staged outside it stay staged.
+fn commit_tree<'r>(repo: &'r Repository, filter: &Filter) -> Result<Tree<'r>, git2::Error> {
     let mut index = repo.index()?;
-    let oid = index.write_tree()?;
-    let tree = repo.find_tree(oid)?;
+    index.read(false)?;
+    if !filter.is_partial() {
+        let oid = index.write_tree()?;
+        return repo.find_tree(oid);
+    }
+
+    let head_tree = head_tree(repo)?;
+    let mut partial = Index::new()?;
+    if let Some(tree) = &head_tree {
+        partial.read_tree(tree)?;
+    }
+
+    let mut options = DiffOptions::new();
+    options.include_typechange(true);
+    let mut diff = repo.diff_tree_to_index(head_tree.as_ref(), Some(&index), Some(&mut options))?;
+    // a move into or out of the pathspec takes both of its paths along
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+    for delta in diff.deltas() {
+        let old_path = delta.old_file().path();
+        let new_path = delta.new_file().path();
+        if !old_path
+            .into_iter()
+            .chain(new_path)
+            .any(|path| filter.matches(path))
+        {
+            continue;
+        }
+        if let (Delta::Deleted | Delta::Renamed, Some(old_path)) = (delta.status(), old_path) {
+            partial.remove_path(old_path)?;
+        }
+        if let (false, Some(new_path)) = (delta.status() == Delta::Deleted, new_path) {
+            let entry = index.get_path(new_path, 0).ok_or_else(|| {
+                git2::Error::from_str(&format!("{} is not in the index", new_path.display()))
+            })?;
+            partial.add(&entry)?;
+        }
+    }
+
+    let oid = partial.write_tree_to(repo)?;
+    repo.find_tree(oid)
+}
+
+/// The `qc.fallbackMessage` template used when no message is given, with
+/// `{files}` and `{count}` filled in from the staged files.
+fn fallback_message(repo: &Repository, files: &[StagedFile]) -> Option<String> {
+    let template = repo
+        .config()
+        .ok()?
+        .get_string("qc.fallbackMessage")
+        .ok()
+        .filter(|template| !template.trim().is_empty())?;
+
+    let paths: Vec<&str> = files.iter().map(|file| file.path.as_str()).collect();
+    Some(
+        template
+            .replace("{files}", &paths.join(", "))
+            .replace("{count}", &files.len().to_string()),
+    )
+}
+
+/// The diff of what is about to be committed against HEAD.
+fn diff<'r>(
+    repo: &'r Repository,
+    files: &[StagedFile],
+    filter: &Filter,
+    dry_run: bool,
+) -> Result<Diff<'r>, git2::Error> {
+    // a fresh repository diffs against the empty tree
+    let head_tree = head_tree(repo)?;
+
+    let mut diff = if dry_run {
+        // What staging everything would produce, read straight from the working tree
+        let mut options = DiffOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .show_untracked_content(true)
+            .include_typechange(true)
+            .disable_pathspec_match(true);
+        for file in files {
+            options.pathspec(&file.path);
+            if let Some(old_path) = &file.old_path {
+                options.pathspec(old_path);
+            }
+        }
+        repo.diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut options))?
+    } else {
+        let tree = commit_tree(repo, filter)?;
+
+        // a file turned symlink is one change, not a delete and an add
+        let mut options = DiffOptions::new();
+        options.include_typechange(true);
+        repo.diff_tree_to_tree(head_tree.as_ref(), Some(&tree), Some(&mut options))?
+    };
+
+    // count moved files by what changed, not as a full delete and add
+    diff.find_similar(Some(
+        DiffFindOptions::new().renames(true).for_untracked(true),
+    ))?;
+
+    Ok(diff)
+}
+
+/// The files in a diff against HEAD, classified like `stage` does.
+fn committed_files(diff: &Diff) -> Vec<StagedFile> {
+    diff.deltas()
+        .filter_map(|delta| {
+            let status = match delta.status() {
+                Delta::Added => git2::Status::INDEX_NEW,
+                Delta::Deleted => git2::Status::INDEX_DELETED,
+                Delta::Renamed => git2::Status::INDEX_RENAMED,
+                Delta::Typechange => git2::Status::INDEX_TYPECHANGE,
+                _ => git2::Status::INDEX_MODIFIED,
+            };
+            let old_path = delta.old_file().path();
+            let path = delta.new_file().path().or(old_path)?;
+            Some(StagedFile {
+                path: path.display().to_string(),
+                old_path: old_path
+                    .filter(|_| status == git2::Status::INDEX_RENAMED)
+                    .map(|old_path| old_path.display().to_string()),
+                status,
+            })
+        })
+        .collect()
+}
 
-    let head_commit = repo.head()?.peel_to_commit()?;
-    let head_tree = head_commit.tree()?;
+/// Read a trimmed line after a cyan prompt, `None` at end of input.
+fn ask(prompt: &str) -> Option<String> {
+    print!("{}", prompt.cyan());
+    stdout().flush().unwrap();
 
-    let diff = repo.diff_tree_to_tree(Some(&head_tree), Some(&tree), None)?;
+    let mut answer = String::new();
+    match io::stdin().read_line(&mut answer) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(answer.trim().to_string()),
+    }
+}
 
-    Ok((diff.stats()?.insertions(), diff.stats()?.deletions()))
+/// Put the index back the way it was before `stage` and exit.
+fn abort(repo: &Repository, snapshot: &IndexSnapshot, message: &str) -> ! {
+    let _ = snapshot.restore(repo);
+    eprintln!("{}", message.red());
+    std::process::exit(1);
 }
 
 fn main() {
+    let args = Args::parse();
 
-    let repo = Repository::discover(".").unwrap_or_else(|_| {
+    let repo = Repository::discover(&args.directory).unwrap_or_else(|_| {
         eprintln!("{}", "Error opening git repo •◠•".red());
         std::process::exit(1);
     });
@@ -110,36 +509,101 @@ fn main() {
     );
 
     // stage changes
-    let files = stage(&repo).unwrap_or_else(|_| {
-        eprintln!("{}", "Error staging files •◠•".red());
+    let snapshot = IndexSnapshot::take(&repo);
+    if !args.dry_run {
+        snapshot.restore_on_interrupt();
+    }
+    state::check(&repo).unwrap_or_else(|e| {
+        eprintln!("{}", format!("{} •◠•", e.message()).red());
         std::process::exit(1);
     });
-    if files.len() == 0 {
-        println!("{}", "No changes to commit •◡•".yellow());
-        std::process::exit(0);
+    let amended = if args.amend {
+        Some(state::amendable(&repo).unwrap_or_else(|e| {
+            eprintln!("{}", format!("{} •◠•", e.message()).red());
+            std::process::exit(1);
+        }))
+    } else {
+        None
+    };
+    // refuse before rewriting anything if the amend couldn't be pushed
+    if let (Some(old), false, Ok(target)) = (&amended, args.no_push, push::target(&repo)) {
+        if let Err(e) = push::lease(&repo, &target, old.id()) {
+            eprintln!("{}", format!("{} •◠•", e.message()).red());
+            std::process::exit(1);
+        }
     }
-    for (path, status) in &files {
-        let print_path = path;
-        match status {
-            &git2::Status::INDEX_NEW => {
-                print!("{}", ("+ ".to_owned() + &print_path).green())
-            }
-            &git2::Status::INDEX_MODIFIED => {
-                print!("{}", ("M ".to_owned() + &print_path).yellow())
+
+    let filter = Filter::new(&repo, &args.directory, &args.pathspec).unwrap_or_else(|e| {
+        eprintln!("{}", format!("Invalid pathspec: {} •◠•", e.message()).red());
+        std::process::exit(1);
+    });
+    let filter = if args.interactive {
+        let candidates = stage(&repo, &filter, true).unwrap_or_else(|e| {
+            eprintln!(
+                "{}",
+                format!("Error reading changes: {} •◠•", e.message()).red()
+            );
+            std::process::exit(1);
+        });
+        if candidates.is_empty() {
+            println!("{}", "No changes to commit •◡•".yellow());
+            std::process::exit(0);
+        }
+        match picker::pick(&candidates) {
+            Ok(Some(paths)) => filter.only(paths),
+            Ok(None) => {
+                eprintln!("{}", "Aborted, nothing committed •◠•".red());
+                std::process::exit(1);
             }
-            &git2::Status::INDEX_DELETED => {
-                print!("{}", ("- ".to_owned() + &print_path).red())
+            Err(e) => {
+                eprintln!("{}", format!("Error picking files: {} •◠•", e).red());
+                std::process::exit(1);
             }
-            _ => continue,
         }
-        println!();
+    } else {
+        filter
+    };
+    let files = if args.patch {
+        hunks::stage(&repo, &filter)
+    } else {
+        stage(&repo, &filter, args.dry_run)
+    };
+    let files = files.unwrap_or_else(|e| {
+        abort(
+            &repo,
+            &snapshot,
+            &format!("Error staging files: {} •◠•", e.message()),
+        )
+    });
+    // commit info
+    let diff = diff(&repo, &files, &filter, args.dry_run)
+        .unwrap_or_else(|_| abort(&repo, &snapshot, "Error reading git info •◠•"));
+    // everything going into the commit, including what was staged before
+    let files = if args.dry_run {
+        files
+    } else {
+        committed_files(&diff)
+    };
+    // amending just the message is fine
+    if files.is_empty() && amended.is_none() {
+        println!("{}", "No changes to commit •◡•".yellow());
+        std::process::exit(0);
     }
+    let stats = diffstat::per_file(&repo, &diff)
+        .unwrap_or_else(|_| abort(&repo, &snapshot, "Error reading git info •◠•"));
+    // summed from the files, so typechanges count the way they're listed
+    let lines_inserted: usize = stats.values().map(|stat| stat.insertions).sum();
+    let lines_deleted: usize = stats.values().map(|stat| stat.deletions).sum();
+
+    let rows: Vec<(String, Color, Option<diffstat::FileStat>)> = files
+        .iter()
+        .map(|file| {
+            let (_, color) = file.marker();
+            (file.label(), color, stats.get(&file.path).copied())
+        })
+        .collect();
+    diffstat::print(&rows);
 
-    // commit info
-    let (lines_inserted, lines_deleted) = lines(&repo).unwrap_or_else(|_| {
-        eprintln!("{}", "Error reading git info •◠•".red());
-        std::process::exit(1);
-    });
     println!(
         "\n{} files staged, {} lines added, {} lines deleted",
         files.len().to_string().yellow(),
@@ -147,39 +611,308 @@ fn main() {
         ("-".to_owned() + &lines_deleted.to_string()).red(),
     );
 
+    let findings = secrets::scan(&repo, &diff).unwrap_or_else(|e| {
+        abort(
+            &repo,
+            &snapshot,
+            &format!("Error scanning for secrets: {} •◠•", e.message()),
+        )
+    });
+    if !findings.is_empty() {
+        println!(
+            "\n{}",
+            "Possible secrets in the staged changes:".red().bold()
+        );
+        for finding in &findings {
+            println!(
+                "{}",
+                format!("  {}:{} {}", finding.path, finding.line, finding.kind).red()
+            );
+        }
+        if !args.allow_secrets && !args.dry_run {
+            abort(
+                &repo,
+                &snapshot,
+                "Refusing to commit secrets, use --allow-secrets to override •◠•",
+            );
+        }
+    }
+
+    let offenders = sizes::check(&repo, &diff, &stats).unwrap_or_else(|e| {
+        abort(
+            &repo,
+            &snapshot,
+            &format!("Error checking file sizes: {} •◠•", e.message()),
+        )
+    });
+    if !offenders.is_empty() {
+        println!();
+        for offender in &offenders {
+            let line = format!("  {} {}", offender.path, offender.reason);
+            if offender.block {
+                println!("{}", line.red());
+            } else {
+                println!("{}", line.yellow());
+            }
+        }
+        if offenders.iter().any(|offender| offender.block) && !args.allow_large && !args.dry_run {
+            abort(
+                &repo,
+                &snapshot,
+                "Refusing to commit these files, use --allow-large to override •◠•",
+            );
+        }
+    }
+
+    if args.diff {
+        println!();
+        if let Err(e) = preview::show(&repo, &diff) {
+            eprintln!(
+                "{}",
+                format!("Error showing diff: {} •◠•", e.message()).red()
+            );
+        }
+    }
+
+    if args.dry_run {
+        if args.no_push {
+            println!("\n{}", "would not push".italic());
+        } else {
+            let pushed = push::target(&repo).and_then(|target| {
+                let lease = match &amended {
+                    Some(old) => push::lease(&repo, &target, old.id())?,
+                    None => None,
+                };
+                Ok((target, lease))
+            });
+            match pushed {
+                Ok((target, lease)) => println!(
+                    "\n{}",
+                    format!(
+                        "would {} {} to {} {}{}",
+                        if lease.is_some() {
+                            "force push"
+                        } else {
+                            "push"
+                        },
+                        target.branch,
+                        target.remote,
+                        target.merge.trim_start_matches("refs/heads/"),
+                        if target.set_upstream {
+                            " (new upstream)"
+                        } else {
+                            ""
+                        }
+                    )
+                    .italic()
+                ),
+                Err(e) => println!("\n{}", format!("would not push: {}", e.message()).italic()),
+            }
+        }
+        println!("{}", "Dry run, nothing committed •◡•".yellow());
+        std::process::exit(0);
+    }
+
     // commit message
-    print!("{}", ": ".cyan());
-    stdout().flush().unwrap();
-    let mut commit_title = String::new();
-    io::stdin()
-        .read_line(&mut commit_title)
-        .expect("Failed to read input");
-    let commit_title = commit_title.trim();
+    let conventional = conventional::enabled(&repo, args.conventional);
+    let mut edit = args.edit;
+    // like git, the template is for messages written here, not given with -m
+    let template = if args.message().is_none() && !args.yes && !conventional && !args.amend {
+        editor::template(&repo).unwrap_or_else(|e| {
+            abort(
+                &repo,
+                &snapshot,
+                &format!("Error reading commit template: {} •◠•", e.message()),
+            )
+        })
+    } else {
+        None
+    };
+    let prefix = match &template {
+        Some(template) => editor::single_line(&repo, template).unwrap_or_else(|e| {
+            abort(
+                &repo,
+                &snapshot,
+                &format!("Error reading commit template: {} •◠•", e.message()),
+            )
+        }),
+        None => None,
+    };
+    let commit_title = match args.message() {
+        Some(message) => message.trim().to_string(),
+        None if args.yes => String::new(),
+        None if conventional && amended.is_none() => conventional::prompt(&repo, &files)
+            .unwrap_or_else(|| abort(&repo, &snapshot, "\nAborted, nothing committed •◠•")),
+        // a template with several lines won't fit on the prompt
+        None if template.is_some() && prefix.is_none() => {
+            edit = true;
+            template.clone().unwrap_or_default()
+        }
+        None => loop {
+            if let Some(old) = &amended {
+                println!(
+                    "{}",
+                    format!("enter keeps \"{}\"", old.summary().unwrap_or_default()).italic()
+                );
+            }
+            let prefix = prefix.as_deref().unwrap_or_default();
+            match ask(&format!(": {}", prefix)) {
+                None => abort(&repo, &snapshot, "\nAborted, nothing committed •◠•"),
+                Some(answer) if answer == "?" => {
+                    if let Err(e) = preview::show(&repo, &diff) {
+                        eprintln!(
+                            "{}",
+                            format!("Error showing diff: {} •◠•", e.message()).red()
+                        );
+                    }
+                }
+                // a trailing backslash continues the message in the editor
+                Some(answer) if answer.ends_with('\\') => {
+                    edit = true;
+                    break format!("{}{}", prefix, answer.trim_end_matches('\\').trim_end());
+                }
+                Some(answer) => break format!("{}{}", prefix, answer),
+            }
+        },
+    };
+    // an amend without a new message keeps the old one
+    let commit_title = match &amended {
+        Some(old) if commit_title.is_empty() => {
+            old.message().unwrap_or_default().trim_end().to_string()
+        }
+        _ => commit_title,
+    };
+    let commit_title = if edit {
+        let source: &[&str] = if amended.is_some() {
+            &["commit", "HEAD"]
+        } else if args.message().is_some() {
+            &["message"]
+        } else if template.is_some() {
+            &["template"]
+        } else {
+            &[]
+        };
+        editor::message(&repo, &commit_title, source, &files, &diff).unwrap_or_else(|e| {
+            abort(
+                &repo,
+                &snapshot,
+                &format!("Error editing commit message: {} •◠•", e.message()),
+            )
+        })
+    } else {
+        commit_title
+    };
+    if let Some(template) = &template {
+        if !commit_title.trim().is_empty()
+            && editor::unchanged(&repo, &commit_title, template).unwrap_or(false)
+        {
+            abort(
+                &repo,
+                &snapshot,
+                "Commit message unchanged from commit.template, nothing committed •◠•",
+            );
+        }
+    }
+    let commit_title = if commit_title.is_empty() {
+        fallback_message(&repo, &files).unwrap_or_else(|| {
+            abort(
+                &repo,
+                &snapshot,
+                "Empty commit message, nothing committed •◠•",
+            )
+        })
+    } else {
+        commit_title
+    };
+    if conventional {
+        if let Err(e) = conventional::validate(&repo, &commit_title) {
+            abort(&repo, &snapshot, &format!("{} •◠•", e));
+        }
+    }
+
+    let problems = lint::check(&repo, &commit_title).unwrap_or_else(|e| {
+        abort(
+            &repo,
+            &snapshot,
+            &format!("Error linting commit message: {} •◠•", e.message()),
+        )
+    });
+    for problem in &problems {
+        let line = format!("  {}", problem.reason);
+        if problem.block {
+            println!("{}", line.red());
+        } else {
+            println!("{}", line.yellow());
+        }
+    }
+    if problems.iter().any(|problem| problem.block) {
+        abort(
+            &repo,
+            &snapshot,
+            &format!("Commit message breaks the rules in {} •◠•", lint::FILE),
+        );
+    }
 
     // commit
-    commit(&repo, commit_title).unwrap_or_else(|_| {
-        eprintln!("{}", "Error committing changes •◠•".red());
-        std::process::exit(1);
+    commit(
+        &repo,
+        &commit_title,
+        !args.no_verify,
+        amended.as_ref(),
+        edit,
+        &filter,
+    )
+    .unwrap_or_else(|e| {
+        abort(
+            &repo,
+            &snapshot,
+            &format!("Error committing changes: {} •◠•", e.message()),
+        )
     });
+    snapshot.forget();
+
+    if args.no_push {
+        print!("\n{}", "committed code ✔ ".green());
+        return;
+    }
 
-    // let current_exe = env::current_exe().expect("Failed to get current executable");
-    
     // push
-    let mut child = Command::new("git")
-        .arg("push")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .unwrap_or_else(|_| {
-            eprintln!("{}", "Unable to call 'git push' •◠•".red());
+    let pushed = push::target(&repo).and_then(|target| {
+        // a rewritten commit that was already pushed has to be forced
+        let lease = match &amended {
+            Some(old) => push::lease(&repo, &target, old.id())?,
+            None => None,
+        };
+        push::push(&repo, &target, lease)?;
+        Ok((target, lease))
+    });
+    match pushed {
+        Ok((target, lease)) => {
+            if lease.is_some() {
+                print!(
+                    "\n{}",
+                    format!("rewrote {}/{}", target.remote, target.branch).italic()
+                );
+            }
+            if target.set_upstream {
+                print!(
+                    "\n{}",
+                    format!(
+                        "{} now tracks {}/{}",
+                        target.branch, target.remote, target.branch
+                    )
+                    .italic()
+                );
+            }
+            print!("\n{}", "pushed code 🚀 ".green())
+        }
+        Err(e) => {
+            // the commit stays, but scripts need to know the push failed
+            eprintln!(
+                "\n{}",
+                format!("Error pushing code: {} •◠•", e.message()).red()
+            );
             std::process::exit(1);
-        });
-
-    let success = child.wait().expect("Failed to wait on child process");
-
-    if !success.success() {
-        eprintln!("\n{}", "Error pushing code •◠•".red());
-    } else {
-        print!("\n{}", "pushed code 🚀 ".green());
+        }
     }
 }
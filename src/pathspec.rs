@@ -0,0 +1,127 @@
+use git2::{Error, Pathspec, PathspecFlags, Repository};
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+/// Pathspecs from the command line, split into includes and `:!`/`:^`/
+/// `:(exclude)` excludes and made relative to the root of the working tree.
+pub struct Filter {
+    include: Option<Pathspec>,
+    exclude: Option<Pathspec>,
+    /// Exact paths picked interactively, on top of the pathspecs
+    only: Option<HashSet<String>>,
+}
+
+impl Filter {
+    /// `base` is the directory the pathspecs are relative to, `:/` makes one
+    /// relative to the root instead.
+    pub fn new(repo: &Repository, base: &Path, specs: &[String]) -> Result<Filter, Error> {
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| Error::from_str("pathspecs need a working tree"))?;
+        let prefix = relative_to(base, workdir)?;
+
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+        for spec in specs {
+            let (is_exclude, spec) = if let Some(rest) = spec.strip_prefix(":(exclude)") {
+                (true, rest)
+            } else if let Some(rest) = spec.strip_prefix(":!").or(spec.strip_prefix(":^")) {
+                (true, rest)
+            } else {
+                (false, spec.as_str())
+            };
+            let spec = match spec.strip_prefix(":/") {
+                Some(rest) => normalize(Path::new(rest)),
+                None => normalize(&prefix.join(spec)),
+            };
+
+            if is_exclude {
+                exclude.push(spec);
+            } else {
+                include.push(spec);
+            }
+        }
+
+        // Excludes alone apply to everything, like git
+        if include.is_empty() && !exclude.is_empty() {
+            include.push(String::new());
+        }
+
+        Ok(Filter {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+            only: None,
+        })
+    }
+
+    /// Narrow the filter down to exactly `paths`.
+    pub fn only(self, paths: HashSet<String>) -> Filter {
+        Filter {
+            only: Some(paths),
+            ..self
+        }
+    }
+
+    /// Whether only some paths are selected, by pathspec or in the picker, so
+    /// the commit has to be limited to them rather than take the whole index.
+    pub fn is_partial(&self) -> bool {
+        self.include.is_some() || self.exclude.is_some() || self.only.is_some()
+    }
+
+    /// Whether a path relative to the working tree root is selected.
+    pub fn matches(&self, path: &Path) -> bool {
+        let included = self
+            .include
+            .as_ref()
+            .is_none_or(|spec| spec.matches_path(path, PathspecFlags::DEFAULT));
+        let excluded = self
+            .exclude
+            .as_ref()
+            .is_some_and(|spec| spec.matches_path(path, PathspecFlags::DEFAULT));
+        let picked = self
+            .only
+            .as_ref()
+            .is_none_or(|paths| paths.contains(path.to_string_lossy().as_ref()));
+        included && !excluded && picked
+    }
+}
+
+fn compile(specs: Vec<String>) -> Result<Option<Pathspec>, Error> {
+    if specs.is_empty() {
+        return Ok(None);
+    }
+    // An empty pattern (the root itself) matches everything
+    if specs.iter().any(|spec| spec.is_empty()) {
+        return Ok(Some(Pathspec::new(["*"])?));
+    }
+    Ok(Some(Pathspec::new(specs)?))
+}
+
+/// `base` relative to the working tree root.
+fn relative_to(base: &Path, workdir: &Path) -> Result<PathBuf, Error> {
+    let base = base
+        .canonicalize()
+        .map_err(|e| Error::from_str(&format!("unable to resolve {}: {}", base.display(), e)))?;
+    let workdir = workdir
+        .canonicalize()
+        .map_err(|e| Error::from_str(&format!("unable to resolve {}: {}", workdir.display(), e)))?;
+
+    base.strip_prefix(&workdir)
+        .map(Path::to_path_buf)
+        .map_err(|_| Error::from_str("pathspec base is outside the repository"))
+}
+
+/// Resolve `.` and `..` lexically and use `/` separators, as pathspecs do.
+fn normalize(path: &Path) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            Component::ParentDir => {
+                parts.pop();
+            }
+            _ => {}
+        }
+    }
+    parts.join("/")
+}
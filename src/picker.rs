@@ -0,0 +1,195 @@
+use crate::StagedFile;
+use colored::*;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, queue, terminal};
+use std::collections::HashSet;
+use std::io::{self, stdout, Write};
+
+enum Row {
+    /// A directory, toggling every file below it
+    Dir {
+        prefix: String,
+        name: String,
+    },
+    File {
+        index: usize,
+        name: String,
+    },
+}
+
+/// Let the user pick which of `files` to commit in a checkbox tree. Returns
+/// the selected paths (old and new for renames), or `None` if cancelled.
+pub fn pick(files: &[StagedFile]) -> io::Result<Option<HashSet<String>>> {
+    let rows = tree(files);
+    let mut selected = vec![true; files.len()];
+
+    terminal::enable_raw_mode()?;
+    queue!(stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+    let result = run(files, &rows, &mut selected);
+    queue!(stdout(), cursor::Show, terminal::LeaveAlternateScreen)?;
+    stdout().flush()?;
+    terminal::disable_raw_mode()?;
+
+    if !result? {
+        return Ok(None);
+    }
+
+    let mut paths = HashSet::new();
+    for (file, _) in files.iter().zip(&selected).filter(|(_, &keep)| keep) {
+        paths.insert(file.path.clone());
+        if let Some(old_path) = &file.old_path {
+            paths.insert(old_path.clone());
+        }
+    }
+    Ok(Some(paths))
+}
+
+/// Handle keys until the selection is confirmed (true) or cancelled (false).
+fn run(files: &[StagedFile], rows: &[Row], selected: &mut [bool]) -> io::Result<bool> {
+    let mut cursor = 0;
+    let mut offset = 0;
+
+    loop {
+        let (_, height) = terminal::size()?;
+        let visible = (height as usize).saturating_sub(2).max(1);
+        if cursor < offset {
+            offset = cursor;
+        } else if cursor >= offset + visible {
+            offset = cursor + 1 - visible;
+        }
+        draw(files, rows, selected, cursor, offset, visible)?;
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            _ => continue,
+        };
+        match key {
+            KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => return Ok(false),
+            KeyEvent { code, .. } => match code {
+                KeyCode::Up | KeyCode::Char('k') => cursor = cursor.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => cursor = (cursor + 1).min(rows.len() - 1),
+                KeyCode::Char(' ') => toggle(files, &rows[cursor], selected),
+                KeyCode::Char('a') => {
+                    let all = selected.iter().all(|&keep| keep);
+                    selected.iter_mut().for_each(|keep| *keep = !all);
+                }
+                KeyCode::Enter => return Ok(true),
+                KeyCode::Esc | KeyCode::Char('q') => return Ok(false),
+                _ => {}
+            },
+        }
+    }
+}
+
+fn draw(
+    files: &[StagedFile],
+    rows: &[Row],
+    selected: &[bool],
+    cursor: usize,
+    offset: usize,
+    visible: usize,
+) -> io::Result<()> {
+    let mut out = stdout();
+    queue!(
+        out,
+        cursor::MoveTo(0, 0),
+        terminal::Clear(terminal::ClearType::All)
+    )?;
+    write!(
+        out,
+        "{}\r\n",
+        "space toggle · a all · enter commit · q cancel"
+            .italic()
+            .cyan()
+    )?;
+
+    for (i, row) in rows.iter().enumerate().skip(offset).take(visible) {
+        let pointer = if i == cursor { ">" } else { " " };
+        let (depth, check, label) = match row {
+            Row::Dir { prefix, name } => {
+                let below: Vec<bool> = files
+                    .iter()
+                    .zip(selected)
+                    .filter(|(file, _)| file.path.starts_with(prefix.as_str()))
+                    .map(|(_, &keep)| keep)
+                    .collect();
+                let check = if below.iter().all(|&keep| keep) {
+                    "[x]"
+                } else if below.iter().any(|&keep| keep) {
+                    "[-]"
+                } else {
+                    "[ ]"
+                };
+                (prefix.matches('/').count() - 1, check, name.bold())
+            }
+            Row::File { index, name } => {
+                let file = &files[*index];
+                let (marker, color) = file.marker();
+                let check = if selected[*index] { "[x]" } else { "[ ]" };
+                (
+                    file.path.matches('/').count(),
+                    check,
+                    format!("{} {}", marker, name).color(color),
+                )
+            }
+        };
+        write!(
+            out,
+            "{} {}{} {}\r\n",
+            pointer,
+            "  ".repeat(depth),
+            check,
+            label
+        )?;
+    }
+
+    out.flush()
+}
+
+fn toggle(files: &[StagedFile], row: &Row, selected: &mut [bool]) {
+    match row {
+        Row::Dir { prefix, .. } => {
+            let below: Vec<usize> = (0..files.len())
+                .filter(|&i| files[i].path.starts_with(prefix.as_str()))
+                .collect();
+            let all = below.iter().all(|&i| selected[i]);
+            for i in below {
+                selected[i] = !all;
+            }
+        }
+        Row::File { index, .. } => selected[*index] = !selected[*index],
+    }
+}
+
+/// Files sorted by path, each preceded by the directories it is in.
+fn tree(files: &[StagedFile]) -> Vec<Row> {
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by(|&a, &b| files[a].path.cmp(&files[b].path));
+
+    let mut rows = Vec::new();
+    let mut seen = HashSet::new();
+    for index in order {
+        let parts: Vec<&str> = files[index].path.split('/').collect();
+        for depth in 0..parts.len() - 1 {
+            let prefix = format!("{}/", parts[..=depth].join("/"));
+            if seen.insert(prefix.clone()) {
+                rows.push(Row::Dir {
+                    prefix,
+                    name: format!("{}/", parts[depth]),
+                });
+            }
+        }
+
+        let mut name = parts[parts.len() - 1].to_string();
+        if let Some(old_path) = &files[index].old_path {
+            name = format!("{} (from {})", name, old_path);
+        }
+        rows.push(Row::File { index, name });
+    }
+
+    rows
+}
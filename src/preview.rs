@@ -0,0 +1,73 @@
+use colored::*;
+use git2::{Diff, DiffFormat, Error, Repository};
+use std::env;
+use std::io::{stdout, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// Show the colored unified diff, through the pager when stdout is a terminal.
+pub fn show(repo: &Repository, diff: &Diff) -> Result<(), Error> {
+    let text = render(diff)?;
+
+    match pager(repo) {
+        Some(pager) if stdout().is_terminal() => page(&pager, &text),
+        _ => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+fn render(diff: &Diff) -> Result<String, Error> {
+    let mut text = String::new();
+
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        let content = String::from_utf8_lossy(line.content());
+        let colored = match line.origin() {
+            '+' => format!("+{}", content).green(),
+            '-' => format!("-{}", content).red(),
+            ' ' => format!(" {}", content).normal(),
+            'F' => content.bold(),
+            'H' => content.cyan(),
+            _ => content.normal(),
+        };
+        text.push_str(&colored.to_string());
+        true
+    })?;
+
+    Ok(text)
+}
+
+/// `GIT_PAGER`, `core.pager`, `PAGER`, then `less`, like git. An empty pager
+/// or `cat` means no pager.
+fn pager(repo: &Repository) -> Option<String> {
+    let pager = env::var("GIT_PAGER")
+        .ok()
+        .or_else(|| repo.config().ok()?.get_string("core.pager").ok())
+        .or_else(|| env::var("PAGER").ok())
+        .unwrap_or_else(|| "less".to_string());
+
+    match pager.trim() {
+        "" | "cat" => None,
+        pager => Some(pager.to_string()),
+    }
+}
+
+fn page(pager: &str, text: &str) -> Result<(), Error> {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(pager).stdin(Stdio::piped());
+    // Same defaults git gives less: quit if short, raw colors, no clearing
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| Error::from_str(&format!("unable to run pager '{}': {}", pager, e)))?;
+    // The pager quitting early closes the pipe, which isn't an error
+    let _ = child.stdin.take().unwrap().write_all(text.as_bytes());
+    child
+        .wait()
+        .map_err(|e| Error::from_str(&format!("pager '{}' failed: {}", pager, e)))?;
+
+    Ok(())
+}
@@ -0,0 +1,275 @@
+use git2::{
+    Config, ConfigLevel, Cred, CredentialType, Direction, Error, ErrorClass, ErrorCode, Oid,
+    PushOptions, RemoteCallbacks, Repository,
+};
+use std::cell::RefCell;
+use std::env;
+use std::path::PathBuf;
+
+/// Where the current branch gets pushed to.
+pub struct Target {
+    pub remote: String,
+    pub branch: String,
+    pub merge: String,
+    /// The branch has no upstream yet and it should be set after pushing.
+    pub set_upstream: bool,
+}
+
+impl Target {
+    pub fn refspec(&self) -> String {
+        format!("refs/heads/{}:{}", self.branch, self.merge)
+    }
+}
+
+/// Resolve the push target for HEAD the way `git push` does with
+/// `push.default=simple`: `branch.<name>.pushRemote`, then `remote.pushDefault`,
+/// then `branch.<name>.remote`, pushing to `branch.<name>.merge`.
+///
+/// A branch without an upstream is pushed to a branch of the same name on
+/// the default remote, like `git push -u`.
+pub fn target(repo: &Repository) -> Result<Target, Error> {
+    // read HEAD itself so an unborn branch still has a name
+    let head = repo.find_reference("HEAD")?;
+    let branch = head
+        .symbolic_target()
+        .and_then(|name| name.strip_prefix("refs/heads/"))
+        .ok_or_else(|| Error::from_str("HEAD is detached"))?
+        .to_string();
+
+    let config = repo.config()?;
+    let remote = config
+        .get_string(&format!("branch.{}.pushRemote", branch))
+        .or_else(|_| config.get_string("remote.pushDefault"))
+        .or_else(|_| config.get_string(&format!("branch.{}.remote", branch)));
+
+    match config.get_string(&format!("branch.{}.merge", branch)) {
+        Ok(merge) => Ok(Target {
+            remote: remote
+                .map_err(|_| Error::from_str(&format!("'{}' has no upstream remote", branch)))?,
+            branch,
+            merge,
+            set_upstream: false,
+        }),
+        Err(_) => Ok(Target {
+            remote: remote.or_else(|_| default_remote(repo))?,
+            merge: format!("refs/heads/{}", branch),
+            branch,
+            set_upstream: true,
+        }),
+    }
+}
+
+/// `origin` if it exists, otherwise the only configured remote.
+fn default_remote(repo: &Repository) -> Result<String, Error> {
+    let remotes = repo.remotes()?;
+    let names: Vec<&str> = remotes.iter().flatten().collect();
+
+    if names.contains(&"origin") {
+        return Ok("origin".to_string());
+    }
+    match names.as_slice() {
+        [name] => Ok(name.to_string()),
+        [] => Err(Error::from_str("no remote configured")),
+        _ => Err(Error::from_str(
+            "several remotes configured, set remote.pushDefault",
+        )),
+    }
+}
+
+/// The commit the upstream is expected to be at, if `old` is the upstream's
+/// tip and replacing it needs a force push. An error if the upstream has moved
+/// past `old`. Only branches with an upstream are checked, anything else was
+/// never pushed.
+pub fn lease(repo: &Repository, target: &Target, old: Oid) -> Result<Option<Oid>, Error> {
+    if target.set_upstream {
+        return Ok(None);
+    }
+    let tracking = match repo.branch_upstream_name(&format!("refs/heads/{}", target.branch)) {
+        Ok(name) => name,
+        Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let tracking = match tracking
+        .as_str()
+        .and_then(|name| repo.refname_to_id(name).ok())
+    {
+        Some(tracking) => tracking,
+        None => return Ok(None),
+    };
+
+    if tracking == old {
+        return Ok(Some(tracking));
+    }
+    // forcing would throw away whatever was pushed on top of it
+    if repo.graph_descendant_of(tracking, old)? {
+        return Err(Error::from_str(&format!(
+            "{}/{} has commits after the one being amended, pull or rebase first",
+            target.remote,
+            target.merge.trim_start_matches("refs/heads/")
+        )));
+    }
+    Ok(None)
+}
+
+/// Record the pushed branch as upstream, like `git push -u`.
+fn set_upstream(repo: &Repository, target: &Target) -> Result<(), Error> {
+    let mut config = repo.config()?.open_level(ConfigLevel::Local)?;
+    config.set_str(&format!("branch.{}.remote", target.branch), &target.remote)?;
+    config.set_str(&format!("branch.{}.merge", target.branch), &target.merge)?;
+    Ok(())
+}
+
+/// Push the branch. With a `lease` the remote branch is force pushed after
+/// checking it is still at that commit. This is weaker than
+/// `git push --force-with-lease`: libgit2 reconnects to push, so the check and
+/// the push are separate requests and a push landing in between is overwritten.
+pub fn push(repo: &Repository, target: &Target, lease: Option<Oid>) -> Result<(), Error> {
+    let config = repo.config()?;
+    let mut remote = repo.find_remote(&target.remote)?;
+
+    if let Some(expected) = lease {
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.credentials(credentials(repo.config()?));
+        remote
+            .connect_auth(Direction::Push, Some(callbacks), None)
+            .map_err(describe)?;
+        let current = remote
+            .list()?
+            .iter()
+            .find(|head| head.name() == target.merge)
+            .map(|head| head.oid());
+        remote.disconnect()?;
+
+        if current != Some(expected) {
+            return Err(Error::from_str(&format!(
+                "rejected (stale info), {} changed on {} since the last fetch",
+                target.merge.trim_start_matches("refs/heads/"),
+                target.remote
+            )));
+        }
+    }
+
+    let rejected = RefCell::new(Vec::new());
+
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(credentials(config));
+    callbacks.sideband_progress(|data| {
+        for line in String::from_utf8_lossy(data).lines() {
+            if !line.trim().is_empty() {
+                println!("remote: {}", line.trim_end());
+            }
+        }
+        true
+    });
+    callbacks.push_update_reference(|refname, status| {
+        if let Some(status) = status {
+            rejected
+                .borrow_mut()
+                .push(format!("{} rejected ({})", refname, status));
+        }
+        Ok(())
+    });
+
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+
+    let refspec = match lease {
+        Some(_) => format!("+{}", target.refspec()),
+        None => target.refspec(),
+    };
+    remote
+        .push(&[refspec], Some(&mut options))
+        .map_err(describe)?;
+
+    let rejected = rejected.take();
+    if !rejected.is_empty() {
+        return Err(Error::from_str(&rejected.join(", ")));
+    }
+
+    if target.set_upstream {
+        set_upstream(repo, target)?;
+    }
+
+    Ok(())
+}
+
+/// Turn libgit2's push errors into the reasons `git push` would give.
+fn describe(e: Error) -> Error {
+    match e.code() {
+        ErrorCode::NotFastForward => {
+            Error::from_str("rejected (non-fast-forward), pull the remote changes first")
+        }
+        ErrorCode::Auth => Error::from_str(&format!("authentication failed: {}", e.message())),
+        _ => e,
+    }
+}
+
+/// Credential callback trying, in order: the SSH agent, default SSH keys,
+/// a token from `GIT_TOKEN`/`GITHUB_TOKEN`, the configured credential helper
+/// and finally the platform default. Each source is tried at most once so a
+/// bad credential doesn't loop forever.
+fn credentials(
+    config: Config,
+) -> impl FnMut(&str, Option<&str>, CredentialType) -> Result<Cred, Error> {
+    let mut tried_agent = false;
+    let mut ssh_keys = default_ssh_keys();
+    let mut tried_token = false;
+    let mut tried_helper = false;
+    let mut tried_default = false;
+
+    move |url, username, allowed| {
+        let ssh_username = username.unwrap_or("git");
+
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(ssh_username);
+        }
+
+        if allowed.contains(CredentialType::SSH_KEY) {
+            if !tried_agent {
+                tried_agent = true;
+                return Cred::ssh_key_from_agent(ssh_username);
+            }
+            if let Some(key) = ssh_keys.pop() {
+                return Cred::ssh_key(ssh_username, None, &key, None);
+            }
+        }
+
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            if !tried_token {
+                tried_token = true;
+                if let Ok(token) = env::var("GIT_TOKEN").or_else(|_| env::var("GITHUB_TOKEN")) {
+                    return Cred::userpass_plaintext("x-access-token", &token);
+                }
+            }
+            if !tried_helper {
+                tried_helper = true;
+                if let Ok(cred) = Cred::credential_helper(&config, url, username) {
+                    return Ok(cred);
+                }
+            }
+        }
+
+        if allowed.contains(CredentialType::DEFAULT) && !tried_default {
+            tried_default = true;
+            return Cred::default();
+        }
+
+        Err(Error::new(
+            ErrorCode::Auth,
+            ErrorClass::Net,
+            format!("no usable credentials for {}", url),
+        ))
+    }
+}
+
+fn default_ssh_keys() -> Vec<PathBuf> {
+    let home = match env::var_os("HOME") {
+        Some(home) => PathBuf::from(home),
+        None => return Vec::new(),
+    };
+    ["id_rsa", "id_ecdsa", "id_ed25519"]
+        .iter()
+        .map(|name| home.join(".ssh").join(name))
+        .filter(|path| path.exists())
+        .collect()
+}
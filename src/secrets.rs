@@ -0,0 +1,108 @@
+use git2::{Diff, DiffFormat, Error, Repository};
+use regex::Regex;
+use std::collections::HashMap;
+
+/// Credentials that should never be committed, by name.
+const PATTERNS: &[(&str, &str)] = &[
+    ("AWS access key", r"\b(AKIA|ASIA)[0-9A-Z]{16}\b"),
+    (
+        "AWS secret key",
+        r#"(?i)aws_?secret_?(access_?)?key\W{0,4}[A-Za-z0-9/+=]{40}\b"#,
+    ),
+    (
+        "private key",
+        r"-----BEGIN ([A-Z]+ )*PRIVATE KEY( BLOCK)?-----",
+    ),
+    (
+        "GitHub token",
+        r"\b(gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{60,})\b",
+    ),
+    ("Slack token", r"\bxox[abposr]-[A-Za-z0-9-]{10,}\b"),
+];
+
+/// A line that looks like it contains a secret.
+pub struct Finding {
+    pub path: String,
+    pub line: u32,
+    pub kind: String,
+}
+
+/// Scan the lines a diff adds for credentials: the built-in patterns, any
+/// `qc.secretPattern` regexes from config, and high-entropy tokens.
+pub fn scan(repo: &Repository, diff: &Diff) -> Result<Vec<Finding>, Error> {
+    let mut patterns = Vec::new();
+    for (kind, pattern) in PATTERNS {
+        patterns.push((kind.to_string(), Regex::new(pattern).unwrap()));
+    }
+    let config = repo.config()?;
+    if let Ok(entries) = config.multivar("qc.secretPattern", None) {
+        for entry in &entries {
+            let pattern = entry?.value().unwrap_or_default().to_string();
+            let regex = Regex::new(&pattern).map_err(|e| {
+                Error::from_str(&format!("invalid qc.secretPattern '{}': {}", pattern, e))
+            })?;
+            patterns.push((format!("match for '{}'", pattern), regex));
+        }
+    }
+
+    let mut findings = Vec::new();
+    diff.print(DiffFormat::Patch, |delta, _, line| {
+        if line.origin() != '+' {
+            return true;
+        }
+        let path = match delta.new_file().path() {
+            Some(path) => path.display().to_string(),
+            None => return true,
+        };
+        let number = line.new_lineno().unwrap_or(0);
+        let content = String::from_utf8_lossy(line.content());
+
+        let kind = patterns
+            .iter()
+            .find(|(_, regex)| regex.is_match(&content))
+            .map(|(kind, _)| kind.clone())
+            .or_else(|| high_entropy(&content).then(|| "high-entropy token".to_string()));
+
+        if let Some(kind) = kind {
+            findings.push(Finding {
+                path,
+                line: number,
+                kind,
+            });
+        }
+        true
+    })?;
+
+    Ok(findings)
+}
+
+/// Whether the line has a long, random looking base64-ish token. Hex is left
+/// out, hashes and checksums are everywhere.
+fn high_entropy(line: &str) -> bool {
+    line.split(|c: char| !(c.is_ascii_alphanumeric() || "+/=_-".contains(c)))
+        .filter(|token| token.len() >= 24)
+        .filter(|token| !token.starts_with("sha"))
+        .filter(|token| {
+            token.chars().any(|c| c.is_ascii_digit())
+                && token.chars().any(|c| c.is_ascii_uppercase())
+                && token.chars().any(|c| c.is_ascii_lowercase())
+        })
+        .any(|token| entropy(token) > 4.5)
+}
+
+/// Shannon entropy in bits per character.
+fn entropy(token: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in token.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+
+    let len = token.len() as f64;
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
@@ -0,0 +1,131 @@
+use git2::{Config, Error, Repository, Signature};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Whether `commit.gpgsign` asks for signed commits.
+pub fn enabled(repo: &Repository) -> Result<bool, Error> {
+    Ok(repo.config()?.get_bool("commit.gpgsign").unwrap_or(false))
+}
+
+/// Sign a commit buffer with the program for `gpg.format` (openpgp, x509 or
+/// ssh) and `user.signingkey`, returning the armored signature.
+pub fn sign(repo: &Repository, committer: &Signature, buffer: &str) -> Result<String, Error> {
+    let config = repo.config()?.snapshot()?;
+    let format = config
+        .get_string("gpg.format")
+        .unwrap_or_else(|_| "openpgp".to_string());
+
+    match format.as_str() {
+        "openpgp" => gpg(&config, committer, buffer, "gpg.openpgp.program", "gpg"),
+        "x509" => gpg(&config, committer, buffer, "gpg.x509.program", "gpgsm"),
+        "ssh" => ssh(&config, buffer),
+        other => Err(Error::from_str(&format!(
+            "unsupported gpg.format '{}'",
+            other
+        ))),
+    }
+}
+
+fn gpg(
+    config: &Config,
+    committer: &Signature,
+    buffer: &str,
+    program_key: &str,
+    default_program: &str,
+) -> Result<String, Error> {
+    let program = config
+        .get_string(program_key)
+        .or_else(|_| config.get_string("gpg.program"))
+        .unwrap_or_else(|_| default_program.to_string());
+    // Like git, sign as the committer when no key is configured.
+    let key = config.get_string("user.signingkey").unwrap_or_else(|_| {
+        format!(
+            "{} <{}>",
+            committer.name().unwrap_or_default(),
+            committer.email().unwrap_or_default()
+        )
+    });
+
+    run(
+        Command::new(&program)
+            .arg("--status-fd=2")
+            .arg("-bsau")
+            .arg(&key),
+        &program,
+        buffer,
+    )
+}
+
+fn ssh(config: &Config, buffer: &str) -> Result<String, Error> {
+    let program = config
+        .get_string("gpg.ssh.program")
+        .unwrap_or_else(|_| "ssh-keygen".to_string());
+    let key = config
+        .get_string("user.signingkey")
+        .map_err(|_| Error::from_str("gpg.format is ssh but user.signingkey is not set"))?;
+
+    let mut command = Command::new(&program);
+    command.args(["-Y", "sign", "-n", "git"]);
+
+    // A literal public key means the private half lives in the ssh agent,
+    // anything else is a path to the key file.
+    let literal = key.strip_prefix("key::").unwrap_or(&key);
+    let temp_key = if literal.starts_with("ssh-") || literal.starts_with("ecdsa-") {
+        let path = env::temp_dir().join(format!("qc-signingkey-{}.pub", std::process::id()));
+        fs::write(&path, literal)
+            .map_err(|e| Error::from_str(&format!("unable to write signing key: {}", e)))?;
+        command.arg("-U").arg("-f").arg(&path);
+        Some(path)
+    } else {
+        command.arg("-f").arg(expand_home(&key));
+        None
+    };
+
+    let signature = run(&mut command, &program, buffer);
+    if let Some(path) = temp_key {
+        let _ = fs::remove_file(path);
+    }
+    signature
+}
+
+/// Feed `buffer` to the signing program on stdin and return its stdout.
+fn run(command: &mut Command, program: &str, buffer: &str) -> Result<String, Error> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::from_str(&format!("unable to run '{}': {}", program, e)))?;
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(buffer.as_bytes())
+        .map_err(|e| Error::from_str(&format!("unable to write to '{}': {}", program, e)))?;
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| Error::from_str(&format!("'{}' failed: {}", program, e)))?;
+    let signature = String::from_utf8_lossy(&output.stdout).to_string();
+
+    if !output.status.success() || signature.trim().is_empty() {
+        return Err(Error::from_str(&format!(
+            "'{}' failed to sign the data: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(signature)
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
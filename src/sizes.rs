@@ -0,0 +1,107 @@
+use crate::diffstat::FileStat;
+use crate::filters;
+use git2::{AttrCheckFlags, Config, Delta, Diff, Error, Repository};
+use std::collections::HashMap;
+
+/// A staged file that is too big, or binary without LFS.
+pub struct Offender {
+    pub path: String,
+    pub reason: String,
+    /// Whether it stops the commit rather than just warning
+    pub block: bool,
+}
+
+/// Check every added or changed file in the diff against `qc.warnSize`
+/// (default 5m), `qc.maxSize` (default 100m) and `qc.binaryFiles` (`warn`,
+/// `block` or `allow`, for binaries not tracked by LFS).
+pub fn check(
+    repo: &Repository,
+    diff: &Diff,
+    stats: &HashMap<String, FileStat>,
+) -> Result<Vec<Offender>, Error> {
+    let config = repo.config()?.snapshot()?;
+    let warn_size = size_setting(&config, "qc.warnSize", 5 << 20)?;
+    let max_size = size_setting(&config, "qc.maxSize", 100 << 20)?;
+    let binary_files = config
+        .get_string("qc.binaryFiles")
+        .unwrap_or_else(|_| "warn".to_string());
+    let odb = repo.odb()?;
+
+    let mut offenders = Vec::new();
+    for delta in diff.deltas() {
+        if delta.status() == Delta::Deleted {
+            continue;
+        }
+        let file = delta.new_file();
+        let path = match file.path() {
+            Some(path) => path,
+            None => continue,
+        };
+
+        // the working tree side of a dry run isn't in the object database,
+        // and a clean filter (e.g. LFS) will commit something else than it
+        let size = if !file.id().is_zero() && odb.exists(file.id()) {
+            odb.read_header(file.id())?.0 as u64
+        } else if filters::clean_command(repo, path)?.is_some() {
+            0
+        } else {
+            repo.workdir()
+                .and_then(|workdir| workdir.join(path).metadata().ok())
+                .map_or(0, |metadata| metadata.len())
+        };
+
+        let display = path.display().to_string();
+        if size > max_size {
+            offenders.push(Offender {
+                reason: format!("is {}, over qc.maxSize of {}", human(size), human(max_size)),
+                path: display,
+                block: true,
+            });
+        } else if size > warn_size {
+            offenders.push(Offender {
+                reason: format!(
+                    "is {}, over qc.warnSize of {}",
+                    human(size),
+                    human(warn_size)
+                ),
+                path: display,
+                block: false,
+            });
+        } else if binary_files != "allow"
+            && stats.get(&display).is_some_and(|stat| stat.binary)
+            && repo.get_attr(path, "filter", AttrCheckFlags::FILE_THEN_INDEX)? != Some("lfs")
+        {
+            offenders.push(Offender {
+                reason: "is binary and not tracked by Git LFS".to_string(),
+                path: display,
+                block: binary_files == "block",
+            });
+        }
+    }
+
+    Ok(offenders)
+}
+
+/// A size from config, which accepts git's k/m/g suffixes.
+fn size_setting(config: &Config, name: &str, default: u64) -> Result<u64, Error> {
+    match config.get_i64(name) {
+        Ok(size) => Ok(size.max(0) as u64),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(default),
+        Err(e) => Err(e),
+    }
+}
+
+fn human(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", size, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
@@ -0,0 +1,59 @@
+use colored::*;
+use git2::{Error, Repository};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Snapshot restored by the signal handler, cleared once the commit is made.
+static PENDING: Mutex<Option<IndexSnapshot>> = Mutex::new(None);
+
+/// The on-disk index as it was before `stage`, so an aborted run can hand
+/// the user back exactly what they had staged.
+#[derive(Clone)]
+pub struct IndexSnapshot {
+    path: PathBuf,
+    contents: Option<Vec<u8>>,
+}
+
+impl IndexSnapshot {
+    pub fn take(repo: &Repository) -> IndexSnapshot {
+        let path = repo.path().join("index");
+        let contents = fs::read(&path).ok();
+        IndexSnapshot { path, contents }
+    }
+
+    pub fn restore(&self, repo: &Repository) -> Result<(), Error> {
+        self.write()
+            .map_err(|e| Error::from_str(&format!("unable to restore the index: {}", e)))?;
+
+        repo.index()?.read(true)
+    }
+
+    fn write(&self) -> io::Result<()> {
+        match &self.contents {
+            Some(contents) => fs::write(&self.path, contents),
+            None => fs::remove_file(&self.path),
+        }
+    }
+
+    /// Restore this snapshot if qc is interrupted (Ctrl-C, SIGTERM, SIGHUP)
+    /// before `forget` is called.
+    pub fn restore_on_interrupt(&self) {
+        *PENDING.lock().unwrap() = Some(self.clone());
+
+        let _ = ctrlc::set_handler(|| {
+            if let Some(snapshot) = PENDING.lock().unwrap().take() {
+                if snapshot.write().is_ok() {
+                    eprintln!("\n{}", "Interrupted, index restored •◠•".red());
+                }
+            }
+            std::process::exit(130);
+        });
+    }
+
+    /// Stop restoring on interrupt, once the staged changes are committed.
+    pub fn forget(&self) {
+        PENDING.lock().unwrap().take();
+    }
+}
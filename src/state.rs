@@ -0,0 +1,89 @@
+use git2::{Commit, Error, ErrorCode, Oid, Repository, RepositoryState};
+use std::fs;
+use std::path::Path;
+
+/// Refuse to run in the middle of operations a commit and push would break.
+/// Merges and single cherry-picks and reverts are fine, `commit` finishes them.
+pub fn check(repo: &Repository) -> Result<(), Error> {
+    let (operation, finish) = match repo.state() {
+        RepositoryState::Clean
+        | RepositoryState::Merge
+        | RepositoryState::CherryPick
+        | RepositoryState::Revert => return Ok(()),
+        // committing would clear .git/sequencer and drop the remaining picks
+        RepositoryState::CherryPickSequence => (
+            "a cherry-pick of several commits",
+            "git cherry-pick --continue",
+        ),
+        RepositoryState::RevertSequence => ("a revert of several commits", "git revert --continue"),
+        RepositoryState::Rebase
+        | RepositoryState::RebaseInteractive
+        | RepositoryState::RebaseMerge => ("a rebase", "git rebase --continue"),
+        RepositoryState::ApplyMailbox | RepositoryState::ApplyMailboxOrRebase => {
+            ("git am", "git am --continue")
+        }
+        RepositoryState::Bisect => ("a bisect", "git bisect reset"),
+    };
+
+    Err(Error::from_str(&format!(
+        "{} is in progress, finish it with '{}' first",
+        operation, finish
+    )))
+}
+
+/// The commit `--amend` replaces. Like git, amending in the middle of a
+/// merge, cherry-pick or revert is refused.
+pub fn amendable(repo: &Repository) -> Result<Commit<'_>, Error> {
+    if repo.state() != RepositoryState::Clean {
+        return Err(Error::from_str(
+            "a merge, cherry-pick or revert is in progress, cannot amend",
+        ));
+    }
+    match repo.head() {
+        Ok(head) => head.peel_to_commit(),
+        Err(e) if e.code() == ErrorCode::UnbornBranch => {
+            Err(Error::from_str("there is no commit to amend yet"))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// The commits being merged in, which become extra parents of the commit.
+pub fn merge_heads(repo: &Repository) -> Result<Vec<Commit<'_>>, Error> {
+    if repo.state() != RepositoryState::Merge {
+        return Ok(Vec::new());
+    }
+
+    // mergehead_foreach needs a mutable repository, MERGE_HEAD is simple to read
+    let merge_head = fs::read_to_string(repo.path().join("MERGE_HEAD"))
+        .map_err(|e| Error::from_str(&format!("unable to read MERGE_HEAD: {}", e)))?;
+
+    merge_head
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| repo.find_commit(Oid::from_str(line.trim())?))
+        .collect()
+}
+
+/// The commit being cherry-picked, whose author the new commit keeps.
+pub fn cherry_pick_head(repo: &Repository) -> Result<Option<Commit<'_>>, Error> {
+    match repo.state() {
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => {
+            let head = repo.find_reference("CHERRY_PICK_HEAD")?.peel_to_commit()?;
+            Ok(Some(head))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Whether a conflicted file still contains conflict markers.
+pub fn has_conflict_markers(path: &Path) -> bool {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(_) => return false,
+    };
+
+    contents
+        .split(|&byte| byte == b'\n')
+        .any(|line| line.starts_with(b"<<<<<<< ") || line.starts_with(b">>>>>>> "))
+}